@@ -0,0 +1,77 @@
+use crate::Mode;
+use std::collections::BTreeMap;
+
+/// Abbreviations defined with `:iabbrev` and `:cabbrev`, the editor keeps one set for every
+/// buffer and one for the buffer being edited.
+#[derive(Debug, Default)]
+pub struct Abbreviations {
+    pub insert: BTreeMap<String, String>,
+    pub command: BTreeMap<String, String>,
+}
+
+impl Abbreviations {
+    fn map_mut(&mut self, mode: &Mode) -> &mut BTreeMap<String, String> {
+        match mode {
+            Mode::Command => &mut self.command,
+            _ => &mut self.insert,
+        }
+    }
+
+    fn map(&self, mode: &Mode) -> &BTreeMap<String, String> {
+        match mode {
+            Mode::Command => &self.command,
+            _ => &self.insert,
+        }
+    }
+
+    pub fn define(&mut self, modes: &[Mode], lhs: &str, rhs: &str) {
+        for mode in modes {
+            self.map_mut(mode).insert(lhs.to_string(), rhs.to_string());
+        }
+    }
+
+    /// Removes `lhs` from `modes`, returns false when it was not defined in any of them.
+    pub fn remove(&mut self, modes: &[Mode], lhs: &str) -> bool {
+        let mut found = false;
+        for mode in modes {
+            found |= self.map_mut(mode).remove(lhs).is_some();
+        }
+        found
+    }
+
+    pub fn get(&self, mode: &Mode, lhs: &str) -> Option<&String> {
+        self.map(mode).get(lhs)
+    }
+
+    /// One line per abbreviation in `modes` like `i  teh  the`, buffer local ones are marked
+    /// with `@` before the expansion.
+    pub fn list(&self, modes: &[Mode], local: bool) -> Vec<String> {
+        let marker = if local { "@" } else { "" };
+        modes
+            .iter()
+            .flat_map(|mode| {
+                let name = if mode == &Mode::Command { 'c' } else { 'i' };
+                self.map(mode).iter().map(move |(lhs, rhs)| format!("{}  {}  {}{}", name, lhs, marker, rhs))
+            })
+            .collect()
+    }
+}
+
+/// Splits the arguments of `:iabbrev` into a `<buffer>` flag, the abbreviation and its
+/// expansion, which may contain spaces.
+pub fn parse_args(args: &str) -> (bool, &str, &str) {
+    let args = args.trim_start();
+    let (local, args) = match args.strip_prefix("<buffer>") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, args),
+    };
+    let (lhs, rhs) = args.split_once(' ').unwrap_or((args, ""));
+    (local, lhs, rhs.trim_start())
+}
+
+#[test]
+fn test_abbreviation_args() {
+    assert_eq!(parse_args(" teh the"), (false, "teh", "the"));
+    assert_eq!(parse_args("<buffer> sig Best regards, me"), (true, "sig", "Best regards, me"));
+    assert_eq!(parse_args(""), (false, "", ""));
+}
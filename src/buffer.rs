@@ -0,0 +1,84 @@
+use crate::{abbrev::Abbreviations, editor::EditorError, hex::HexView, undo::Undo};
+use ropey::Rope;
+
+/// A file in the buffer list. The buffer being edited keeps its text and state in `Editor`,
+/// its entry in the list is only filled in again when another buffer is switched to.
+#[derive(Debug, Default)]
+pub struct Buffer {
+    /// Number shown by `:ls`, it never changes while the buffer exists.
+    pub number: usize,
+    pub rope: Rope,
+    pub file_path: Option<String>,
+    /// Cursor as (column, line) and the first line on screen.
+    pub cursor: (usize, usize),
+    pub top: usize,
+    pub modified: bool,
+    pub readonly: bool,
+    pub undo: Undo,
+    pub filetype: String,
+    pub fileformat: String,
+    pub fileencoding: String,
+    pub pairs: String,
+    pub local_abbreviations: Abbreviations,
+    pub largefile: bool,
+    pub hex: Option<HexView>,
+}
+
+impl Buffer {
+    pub fn name(&self) -> &str {
+        self.file_path.as_deref().unwrap_or("[No Name]")
+    }
+}
+
+/// Number of the buffer `arg` of `:b` and `:bdelete` names, either its number or a part of
+/// its name that only one buffer has. A full name wins over names it is part of.
+pub fn find(buffers: &[Buffer], arg: &str) -> Result<usize, EditorError> {
+    if let Ok(number) = arg.parse::<usize>() {
+        return match buffers.iter().any(|buffer| buffer.number == number) {
+            true => Ok(number),
+            false => Err(EditorError::NoSuchBuffer(arg.to_string())),
+        };
+    }
+    if let Some(buffer) = buffers.iter().find(|buffer| buffer.file_path.as_deref() == Some(arg)) {
+        return Ok(buffer.number);
+    }
+    let found = buffers.iter().filter(|buffer| buffer.name().contains(arg)).collect::<Vec<&Buffer>>();
+    match found.as_slice() {
+        [buffer] => Ok(buffer.number),
+        [] => Err(EditorError::NoSuchBuffer(arg.to_string())),
+        _ => Err(EditorError::MoreThanOneMatch(arg.to_string())),
+    }
+}
+
+#[test]
+fn test_find_buffer() {
+    let buffer = |number: usize, name: &str| Buffer { number, file_path: Some(name.to_string()), ..Buffer::default() };
+    let buffers = [buffer(1, "src/main.rs"), buffer(3, "src/editor.rs"), buffer(4, "main.rs")];
+    assert_eq!(find(&buffers, "3").unwrap(), 3);
+    assert_eq!(find(&buffers, "edit").unwrap(), 3);
+    assert_eq!(find(&buffers, "main.rs").unwrap(), 4);
+    assert!(find(&buffers, "2").is_err());
+    assert!(find(&buffers, "rs").is_err());
+}
+
+#[test]
+fn test_buffer_commands() {
+    use crate::{editor::Editor, keymapper::execute_command};
+    let other = std::env::temp_dir().join("revim-buffer-test").join("other.txt");
+    let mut editor = Editor::new(Rope::from_str("one\n"), None);
+    execute_command(&mut editor, &format!("e {}", other.display()));
+    assert_eq!((editor.buffer, editor.buffers.len()), (2, 2));
+    editor.modified = true;
+    execute_command(&mut editor, "b 1");
+    assert!(matches!(editor.error, EditorError::NoWriteSinceLastChange(_)));
+    execute_command(&mut editor, "bd");
+    assert_eq!((editor.buffer, editor.buffers.len()), (2, 2));
+    // Deleting the buffer being edited goes back to the alternate one.
+    execute_command(&mut editor, "bd!");
+    assert_eq!((editor.buffer, editor.buffers.len()), (1, 1));
+    assert_eq!(editor.rope.to_string(), "one\n");
+    // The last buffer is replaced with an empty one.
+    execute_command(&mut editor, "bd");
+    assert_eq!((editor.buffer, editor.buffers.len()), (2, 1));
+    assert_eq!(editor.rope.len_chars(), 0);
+}
@@ -1,6 +1,7 @@
-use crate::Rope;
-use std::fs::{metadata, OpenOptions};
-use std::io::BufReader;
+use crate::{editor::EditorError, encoding, largefile::Loader, Rope};
+use std::fs::{metadata, remove_file, rename, File, Metadata, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
 // External Crates
 use clap::{crate_version, App, Arg};
@@ -17,27 +18,156 @@ pub fn argparser() -> Option<String> {
         )
         .get_matches();
 
-    match matches.value_of("in_file") {
-        Some(v) => Some(v.to_string()),
-        None => None,
-    }
+    matches.value_of("in_file").map(|v| v.to_string())
 }
 
 // wrapper around Rope for a drity flag.
+#[cfg(test)]
 pub fn from_path(path: Option<String>) -> (Rope, Option<String>) {
-    let text = path
-        .as_ref()
-        .filter(|path| metadata(&path).is_ok())
-        .map_or_else(Rope::new, |path| {
-            let file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(path)
-                .expect("Problem opening the file");
-
-            Rope::from_reader(BufReader::new(file)).unwrap()
-        });
-
-    (text, path)
+    let text = path.as_deref().and_then(|path| read_path(path, None).ok()).map(|(rope, _)| rope);
+    (text.unwrap_or_default(), path)
+}
+
+/// Like `read_path`, but of a file bigger than `largefile` megabytes only the first chunk is
+/// read and the `Loader` for the rest comes with it.
+pub fn open_path(
+    path: &str,
+    encoding: Option<&str>,
+    largefile: usize,
+) -> Result<(Rope, &'static str, Option<Loader>), EditorError> {
+    let known = match encoding {
+        Some(name) => Some(known_encoding(name)?),
+        None => None,
+    };
+    match Loader::open(path, largefile, known) {
+        Ok(Some((text, encoding, loader))) => Ok((Rope::from_str(&text), encoding, Some(loader))),
+        _ => read_path(path, encoding).map(|(rope, encoding)| (rope, encoding, None)),
+    }
+}
+
+/// Reads `path` into a rope and returns the encoding it was read in, `encoding` or the one
+/// `encoding::detect` finds. A file that does not exist yet is empty.
+pub fn read_path(path: &str, encoding: Option<&str>) -> Result<(Rope, &'static str), EditorError> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Rope::new(), "utf-8")),
+        Err(e) => return Err(EditorError::Io(format!("{}: {}", path, e))),
+    };
+    let encoding = match encoding {
+        Some(name) => known_encoding(name)?,
+        None => encoding::detect(&bytes),
+    };
+    Ok((Rope::from_str(&encoding::decode(&bytes, encoding)), encoding))
+}
+
+fn known_encoding(name: &str) -> Result<&'static str, EditorError> {
+    encoding::NAMES
+        .iter()
+        .find(|known| **known == name)
+        .copied()
+        .ok_or_else(|| EditorError::InvalidArgument(name.to_string()))
+}
+
+/// Writes `rope` to `path` and returns the number of bytes written. The text goes to a
+/// temporary file next to the target first, which is synced and renamed over it, so a failed
+/// write leaves the old file as it was. A symlink is followed and the target keeps its mode
+/// and, where permitted, its owner. The text is written in `encoding`. A read-only file is
+/// only replaced with `force`, the rename alone would not stop it.
+pub fn write_to_path(rope: &Rope, path: &str, encoding: &str, force: bool) -> Result<usize, EditorError> {
+    let failed = |error: std::io::Error| EditorError::WriteFailed(format!("{}: {}", path, error));
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let old = metadata(&target).ok();
+    if !force && old.as_ref().is_some_and(|old| old.permissions().readonly()) {
+        return Err(EditorError::ReadOnly(path.to_string()));
+    }
+    let dir = target.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let name = target.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let temp = dir.join(format!(".{}.{}.tmp", name, std::process::id()));
+    let result = write_temp(rope, encoding, &temp, old);
+    let result = result.and_then(|bytes| rename(&temp, &target).map(|_| bytes));
+    if let Err(e) = result {
+        remove_file(&temp).ok();
+        return Err(failed(e));
+    }
+    // The rename only survives a crash once the directory is synced too.
+    #[cfg(unix)]
+    File::open(dir).and_then(|dir| dir.sync_all()).ok();
+    result.map_err(failed)
+}
+
+/// Writes `rope` in `encoding` to the temporary file a chunk at a time and syncs it, giving it
+/// the permissions of the file it replaces. Returns the number of bytes written.
+fn write_temp(rope: &Rope, encoding: &str, temp: &Path, old: Option<Metadata>) -> std::io::Result<usize> {
+    let file = OpenOptions::new().write(true).create_new(true).open(temp)?;
+    if let Some(old) = &old {
+        file.set_permissions(old.permissions())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            std::os::unix::fs::fchown(&file, Some(old.uid()), Some(old.gid())).ok();
+        }
+    }
+    let mut writer = BufWriter::new(file);
+    let mut written = encoding::bom(encoding).len();
+    writer.write_all(encoding::bom(encoding))?;
+    for chunk in rope.chunks() {
+        let bytes = encoding::encode_chunk(chunk, encoding).map_err(|c| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?} cannot be written in {}", c, encoding))
+        })?;
+        written += bytes.len();
+        writer.write_all(&bytes)?;
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    Ok(written)
+}
+
+#[test]
+fn test_write_to_path() {
+    let path = std::env::temp_dir().join(format!("revim-write-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap_or_default().to_string();
+    let rope = Rope::from_str("one\r\ntwo\n");
+    assert_eq!(write_to_path(&rope, &path, "utf-8", false).ok(), Some(9));
+    assert_eq!(std::fs::read_to_string(&path).ok().as_deref(), Some("one\r\ntwo\n"));
+    assert!(write_to_path(&Rope::from_str("\u{263A}"), &path, "latin1", false).is_err());
+    assert_eq!(write_to_path(&Rope::from_str("caf\u{e9}"), &path, "latin1", false).ok(), Some(4));
+    let (rope, encoding) = read_path(&path, None).unwrap();
+    assert_eq!((rope.to_string().as_str(), encoding), ("caf\u{e9}", "latin1"));
+    std::fs::remove_file(&path).ok();
+    assert!(write_to_path(&rope, "/no/such/dir/file.txt", "utf-8", false).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_write_keeps_mode_and_symlink() {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = std::env::temp_dir().join(format!("revim-atomic-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (file, link) = (dir.join("file.txt"), dir.join("link.txt"));
+    std::fs::write(&file, "old\n").unwrap();
+    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o640)).unwrap();
+    std::os::unix::fs::symlink(&file, &link).unwrap();
+    write_to_path(&Rope::from_str("new\n"), link.to_str().unwrap(), "utf-8", false).unwrap();
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "new\n");
+    assert!(std::fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+    assert_eq!(metadata(&file).unwrap().permissions().mode() & 0o777, 0o640);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 2);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_write_read_only_needs_force() {
+    use std::os::unix::fs::PermissionsExt;
+    let path = std::env::temp_dir().join(format!("revim-readonly-{}.txt", std::process::id()));
+    std::fs::write(&path, "old\n").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o444)).unwrap();
+    let name = path.to_str().unwrap_or_default();
+    let result = write_to_path(&Rope::from_str("new\n"), name, "utf-8", false);
+    assert!(matches!(result, Err(EditorError::ReadOnly(_))));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "old\n");
+    assert_eq!(write_to_path(&Rope::from_str("new\n"), name, "utf-8", true).ok(), Some(4));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "new\n");
+    assert_eq!(metadata(&path).unwrap().permissions().mode() & 0o777, 0o444);
+    std::fs::remove_file(&path).ok();
 }
@@ -0,0 +1,53 @@
+/// Comments out `lines` with `markers` at their smallest indent, or removes the markers when
+/// every non blank line is already commented. Blank lines are left alone.
+pub fn toggle_comment(lines: &[String], markers: (&str, &str)) -> Vec<String> {
+    let (open, close) = markers;
+    let text = lines.iter().filter(|line| !line.trim().is_empty());
+    let commented = text.clone().all(|line| line.trim_start().starts_with(open));
+    if commented {
+        return lines.iter().map(|line| uncomment(line, markers)).collect();
+    }
+    let indent = text.map(|line| line.len() - line.trim_start().len()).min().unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| {
+            if line.trim().is_empty() {
+                return line.clone();
+            }
+            let (indent, rest) = line.split_at(indent);
+            let close = if close.is_empty() { String::new() } else { format!(" {}", close) };
+            format!("{}{} {}{}", indent, open, rest, close)
+        })
+        .collect()
+}
+
+/// Removes the comment markers and the single space next to each of them from `line`.
+fn uncomment(line: &str, (open, close): (&str, &str)) -> String {
+    let indent = line.len() - line.trim_start().len();
+    let rest = match line[indent..].strip_prefix(open) {
+        Some(rest) => rest,
+        None => return line.to_string(),
+    };
+    let rest = rest.strip_prefix(' ').unwrap_or(rest);
+    let rest = match rest.trim_end().strip_suffix(close).filter(|_| !close.is_empty()) {
+        Some(rest) => rest.strip_suffix(' ').unwrap_or(rest),
+        None => rest,
+    };
+    format!("{}{}", &line[..indent], rest)
+}
+
+#[test]
+fn test_toggle_comment_rust() {
+    let lines = ["    let a = 1;", "", "        a += 1;"].iter().map(|l| l.to_string()).collect::<Vec<_>>();
+    let commented = toggle_comment(&lines, ("//", ""));
+    assert_eq!(commented, vec!["    // let a = 1;", "", "    //     a += 1;"]);
+    assert_eq!(toggle_comment(&commented, ("//", "")), lines);
+}
+
+#[test]
+fn test_toggle_comment_markdown() {
+    let lines = vec!["Some *text*".to_string()];
+    let commented = toggle_comment(&lines, ("<!--", "-->"));
+    assert_eq!(commented, vec!["<!-- Some *text* -->"]);
+    assert_eq!(toggle_comment(&commented, ("<!--", "-->")), lines);
+}
@@ -0,0 +1,169 @@
+use crate::edit::is_keyword;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ropey::Rope;
+use std::path::{Path, PathBuf};
+
+/// Insert mode completion shown in the popup menu.
+#[derive(Debug)]
+pub struct Completion {
+    pub items: Vec<String>,
+    /// Item inserted in the text, `None` while the originally typed text is shown.
+    pub selected: Option<usize>,
+    /// Text that was typed before completion started.
+    pub original: String,
+    /// Line and column the completed text starts at.
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Completion {
+    pub fn new(items: Vec<String>, original: String, line: usize, col: usize) -> Self {
+        Self {
+            items,
+            selected: None,
+            original,
+            line,
+            col,
+        }
+    }
+
+    /// Moves the selection `step` items, passing through the original text at either end.
+    pub fn select(&mut self, step: isize) {
+        let len = self.items.len() as isize + 1;
+        let current = self.selected.map_or(0, |i| i as isize + 1);
+        let next = (current + step).rem_euclid(len);
+        self.selected = if next == 0 { None } else { Some(next as usize - 1) };
+    }
+
+    /// Text that currently stands in the buffer for this completion.
+    pub fn text(&self) -> &str {
+        self.selected.map_or(&self.original, |i| &self.items[i])
+    }
+}
+
+/// Keys that keep the completion popup open, or that close it themselves: `Ctrl-E` puts back
+/// the original text and `Ctrl-Y` keeps the selected item.
+pub fn is_completion_key(key: &KeyEvent) -> bool {
+    key.modifiers == KeyModifiers::CONTROL && matches!(key.code, KeyCode::Char('n' | 'p' | 'x' | 'f' | 'e' | 'y'))
+}
+
+/// Words starting with `prefix` in `ropes`. Words of the first rope come first, starting at
+/// char `index` and wrapping around, the word being typed at `index` is skipped.
+pub fn keywords(ropes: &[&Rope], prefix: &str, index: usize) -> Vec<String> {
+    let mut found = Vec::new();
+    for (n, rope) in ropes.iter().enumerate() {
+        let mut words = Vec::new();
+        let mut word = String::new();
+        for (i, c) in rope.chars().chain(std::iter::once(' ')).enumerate() {
+            if is_keyword(c) {
+                word.push(c);
+                continue;
+            }
+            if word.starts_with(prefix) && word != prefix {
+                words.push((i - word.chars().count(), word.clone()));
+            }
+            word.clear();
+        }
+        if n == 0 {
+            words.retain(|(start, _)| *start != index);
+            let split = words.iter().position(|(start, _)| *start > index).unwrap_or(words.len());
+            words.rotate_left(split);
+        }
+        for (_, word) in words {
+            if !found.contains(&word) {
+                found.push(word);
+            }
+        }
+    }
+    found
+}
+
+/// Keys that keep the wildmenu of the command line open.
+pub fn is_wildmenu_key(key: &KeyEvent) -> bool {
+    matches!(key.code, KeyCode::Tab | KeyCode::BackTab)
+}
+
+/// Names from `names` that start with `prefix`, in order.
+pub fn matching<S: AsRef<str>>(names: &[S], prefix: &str) -> Vec<String> {
+    names
+        .iter()
+        .map(|name| name.as_ref())
+        .filter(|name| name.starts_with(prefix))
+        .map(String::from)
+        .collect()
+}
+
+/// Chars a path typed in the text can be made of.
+pub fn is_path_char(c: char) -> bool {
+    c.is_alphanumeric() || "/._-~+@%\\".contains(c)
+}
+
+/// Entries of the directory `typed` points into whose name starts with the part after its
+/// last slash. Relative paths start from `base`, directories end with a slash.
+pub fn paths(base: &Path, typed: &str) -> Vec<String> {
+    let (dir, name) = match typed.rfind('/') {
+        Some(i) => (&typed[..=i], &typed[i + 1..]),
+        None => ("", typed),
+    };
+    let dir = match dir.strip_prefix("~/") {
+        Some(rest) => std::env::var("HOME").map(|home| PathBuf::from(home).join(rest)).unwrap_or_default(),
+        None if dir.starts_with('/') => PathBuf::from(dir),
+        None => base.join(dir),
+    };
+    let entries = match std::fs::read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { &dir }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut found = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            if !file_name.starts_with(name) || file_name.starts_with('.') && !name.starts_with('.') {
+                return None;
+            }
+            let is_dir = entry.path().is_dir();
+            Some(if is_dir { format!("{}/", file_name) } else { file_name })
+        })
+        .collect::<Vec<String>>();
+    found.sort();
+    found
+}
+
+#[test]
+fn test_paths_from_base() {
+    let base = std::env::temp_dir().join(format!("revim-paths-{}", std::process::id()));
+    std::fs::create_dir_all(base.join("src")).unwrap();
+    std::fs::write(base.join("src/main.rs"), "").unwrap();
+    std::fs::write(base.join("source.txt"), "").unwrap();
+    std::fs::write(base.join(".hidden"), "").unwrap();
+    assert_eq!(paths(&base, "s"), vec!["source.txt", "src/"]);
+    assert_eq!(paths(&base, "src/"), vec!["main.rs"]);
+    assert_eq!(paths(&base, ".h"), vec![".hidden"]);
+    std::fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn test_keywords_order() {
+    let rope = Rope::from_str("apple apricot ap avocado\napply apple");
+    assert_eq!(keywords(&[&rope], "ap", 14), vec!["apply", "apple", "apricot"]);
+    assert_eq!(keywords(&[&rope], "zz", 0), Vec::<String>::new());
+}
+
+#[test]
+fn test_completion_select_cycles() {
+    let mut completion = Completion::new(vec!["a1".into(), "a2".into()], "a".into(), 0, 0);
+    completion.select(1);
+    assert_eq!(completion.text(), "a1");
+    completion.select(-1);
+    assert_eq!(completion.text(), "a");
+    completion.select(-1);
+    assert_eq!(completion.text(), "a2");
+}
+
+#[test]
+fn test_completion_keys() {
+    let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+    assert!(is_completion_key(&ctrl('e')) && is_completion_key(&ctrl('y')));
+    assert!(!is_completion_key(&ctrl('w')));
+    assert!(!is_completion_key(&KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE)));
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+/// Digraphs from RFC 1345 typed with `Ctrl-K {char1} {char2}`: accented Latin letters, Greek
+/// (`*`), Cyrillic (`=`), punctuation, arrows, math and box drawing symbols.
+const DIGRAPHS: &[(&str, char)] = &[
+    ("A!", 'À'), ("A'", 'Á'), ("A>", 'Â'), ("A?", 'Ã'), ("A-", 'Ā'), ("A(", 'Ă'), ("A:", 'Ä'),
+    ("A0", 'Å'), ("A;", 'Ą'), ("a!", 'à'), ("a'", 'á'), ("a>", 'â'), ("a?", 'ã'), ("a-", 'ā'),
+    ("a(", 'ă'), ("a:", 'ä'), ("a0", 'å'), ("a;", 'ą'), ("C'", 'Ć'), ("C>", 'Ĉ'), ("C.", 'Ċ'),
+    ("C<", 'Č'), ("C,", 'Ç'), ("c'", 'ć'), ("c>", 'ĉ'), ("c.", 'ċ'), ("c<", 'č'), ("c,", 'ç'),
+    ("D<", 'Ď'), ("d<", 'ď'), ("E!", 'È'), ("E'", 'É'), ("E>", 'Ê'), ("E-", 'Ē'), ("E(", 'Ĕ'),
+    ("E.", 'Ė'), ("E:", 'Ë'), ("E<", 'Ě'), ("E;", 'Ę'), ("e!", 'è'), ("e'", 'é'), ("e>", 'ê'),
+    ("e-", 'ē'), ("e(", 'ĕ'), ("e.", 'ė'), ("e:", 'ë'), ("e<", 'ě'), ("e;", 'ę'), ("G>", 'Ĝ'),
+    ("G(", 'Ğ'), ("G.", 'Ġ'), ("G,", 'Ģ'), ("g>", 'ĝ'), ("g(", 'ğ'), ("g.", 'ġ'), ("g,", 'ģ'),
+    ("H>", 'Ĥ'), ("h>", 'ĥ'), ("I!", 'Ì'), ("I'", 'Í'), ("I>", 'Î'), ("I?", 'Ĩ'), ("I-", 'Ī'),
+    ("I(", 'Ĭ'), ("I.", 'İ'), ("I:", 'Ï'), ("I;", 'Į'), ("i!", 'ì'), ("i'", 'í'), ("i>", 'î'),
+    ("i?", 'ĩ'), ("i-", 'ī'), ("i(", 'ĭ'), ("i:", 'ï'), ("i;", 'į'), ("J>", 'Ĵ'), ("j>", 'ĵ'),
+    ("K,", 'Ķ'), ("k,", 'ķ'), ("L'", 'Ĺ'), ("L<", 'Ľ'), ("L,", 'Ļ'), ("l'", 'ĺ'), ("l<", 'ľ'),
+    ("l,", 'ļ'), ("N'", 'Ń'), ("N?", 'Ñ'), ("N<", 'Ň'), ("N,", 'Ņ'), ("n'", 'ń'), ("n?", 'ñ'),
+    ("n<", 'ň'), ("n,", 'ņ'), ("O!", 'Ò'), ("O'", 'Ó'), ("O>", 'Ô'), ("O?", 'Õ'), ("O-", 'Ō'),
+    ("O(", 'Ŏ'), ("O:", 'Ö'), ("O\"", 'Ő'), ("o!", 'ò'), ("o'", 'ó'), ("o>", 'ô'), ("o?", 'õ'),
+    ("o-", 'ō'), ("o(", 'ŏ'), ("o:", 'ö'), ("o\"", 'ő'), ("R'", 'Ŕ'), ("R<", 'Ř'), ("R,", 'Ŗ'),
+    ("r'", 'ŕ'), ("r<", 'ř'), ("r,", 'ŗ'), ("S'", 'Ś'), ("S>", 'Ŝ'), ("S<", 'Š'), ("S,", 'Ş'),
+    ("s'", 'ś'), ("s>", 'ŝ'), ("s<", 'š'), ("s,", 'ş'), ("T<", 'Ť'), ("T,", 'Ţ'), ("t<", 'ť'),
+    ("t,", 'ţ'), ("U!", 'Ù'), ("U'", 'Ú'), ("U>", 'Û'), ("U?", 'Ũ'), ("U-", 'Ū'), ("U(", 'Ŭ'),
+    ("U:", 'Ü'), ("U0", 'Ů'), ("U\"", 'Ű'), ("U;", 'Ų'), ("u!", 'ù'), ("u'", 'ú'), ("u>", 'û'),
+    ("u?", 'ũ'), ("u-", 'ū'), ("u(", 'ŭ'), ("u:", 'ü'), ("u0", 'ů'), ("u\"", 'ű'), ("u;", 'ų'),
+    ("W>", 'Ŵ'), ("w>", 'ŵ'), ("Y'", 'Ý'), ("Y>", 'Ŷ'), ("Y:", 'Ÿ'), ("y'", 'ý'), ("y>", 'ŷ'),
+    ("y:", 'ÿ'), ("Z'", 'Ź'), ("Z.", 'Ż'), ("Z<", 'Ž'), ("z'", 'ź'), ("z.", 'ż'), ("z<", 'ž'),
+    ("a*", 'α'), ("A*", 'Α'), ("b*", 'β'), ("B*", 'Β'), ("g*", 'γ'), ("G*", 'Γ'), ("d*", 'δ'),
+    ("D*", 'Δ'), ("e*", 'ε'), ("E*", 'Ε'), ("z*", 'ζ'), ("Z*", 'Ζ'), ("y*", 'η'), ("Y*", 'Η'),
+    ("h*", 'θ'), ("H*", 'Θ'), ("i*", 'ι'), ("I*", 'Ι'), ("k*", 'κ'), ("K*", 'Κ'), ("l*", 'λ'),
+    ("L*", 'Λ'), ("m*", 'μ'), ("M*", 'Μ'), ("n*", 'ν'), ("N*", 'Ν'), ("c*", 'ξ'), ("C*", 'Ξ'),
+    ("o*", 'ο'), ("O*", 'Ο'), ("p*", 'π'), ("P*", 'Π'), ("r*", 'ρ'), ("R*", 'Ρ'), ("s*", 'σ'),
+    ("S*", 'Σ'), ("t*", 'τ'), ("T*", 'Τ'), ("u*", 'υ'), ("U*", 'Υ'), ("f*", 'φ'), ("F*", 'Φ'),
+    ("x*", 'χ'), ("X*", 'Χ'), ("q*", 'ψ'), ("Q*", 'Ψ'), ("w*", 'ω'), ("W*", 'Ω'), ("*s", 'ς'),
+    ("NS", '\u{a0}'), ("!I", '¡'), ("Ct", '¢'), ("Pd", '£'), ("Cu", '¤'), ("Ye", '¥'),
+    ("BB", '¦'), ("SE", '§'), ("':", '¨'), ("Co", '©'), ("-a", 'ª'), ("<<", '«'), ("NO", '¬'),
+    ("--", '\u{ad}'), ("Rg", '®'), ("'m", '¯'), ("DG", '°'), ("+-", '±'), ("2S", '²'),
+    ("3S", '³'), ("''", '´'), ("My", 'µ'), ("PI", '¶'), (".M", '·'), ("',", '¸'), ("1S", '¹'),
+    ("-o", 'º'), (">>", '»'), ("14", '¼'), ("12", '½'), ("34", '¾'), ("?I", '¿'), ("AE", 'Æ'),
+    ("D-", 'Ð'), ("*X", '×'), ("O/", 'Ø'), ("TH", 'Þ'), ("ss", 'ß'), ("ae", 'æ'), ("d-", 'ð'),
+    ("-:", '÷'), ("o/", 'ø'), ("th", 'þ'), ("D/", 'Đ'), ("d/", 'đ'), ("L/", 'Ł'), ("l/", 'ł'),
+    ("OE", 'Œ'), ("oe", 'œ'), ("H/", 'Ħ'), ("h/", 'ħ'), ("i.", 'ı'), ("IJ", 'Ĳ'), ("ij", 'ĳ'),
+    ("kk", 'ĸ'), ("NG", 'Ŋ'), ("ng", 'ŋ'), ("T/", 'Ŧ'), ("t/", 'ŧ'), ("'n", 'ŉ'), ("L.", 'Ŀ'),
+    ("l.", 'ŀ'), ("-N", '–'), ("-M", '—'), ("'6", '‘'), ("'9", '’'), (".9", '‚'), ("9'", '‛'),
+    ("\"6", '“'), ("\"9", '”'), (":9", '„'), ("9\"", '‟'), ("/-", '†'), ("/=", '‡'),
+    ("..", '‥'), (",.", '…'), ("%0", '‰'), ("1'", '′'), ("2'", '″'), ("<1", '‹'), (">1", '›'),
+    ("Eu", '€'), ("=e", '€'), ("=R", '₽'), ("oC", '℃'), ("co", '℅'), ("oF", '℉'), ("N0", '№'),
+    ("TM", '™'), ("Om", 'Ω'), ("<-", '←'), ("-!", '↑'), ("->", '→'), ("-v", '↓'), ("<>", '↔'),
+    ("UD", '↕'), ("<=", '⇐'), ("=>", '⇒'), ("==", '⇔'), ("FA", '∀'), ("dP", '∂'), ("TE", '∃'),
+    ("/0", '∅'), ("DE", '∆'), ("NB", '∇'), ("(-", '∈'), ("-)", '∋'), ("*P", '∏'), ("+Z", '∑'),
+    ("-2", '−'), ("-+", '∓'), ("*-", '∗'), ("Ob", '∘'), ("Sb", '∙'), ("RT", '√'), ("0(", '∝'),
+    ("00", '∞'), ("-L", '∟'), ("-V", '∠'), ("PP", '∥'), ("AN", '∧'), ("OR", '∨'), ("(U", '∩'),
+    (")U", '∪'), ("In", '∫'), ("DI", '∬'), ("Io", '∮'), (".:", '∴'), (":.", '∵'), (":R", '∶'),
+    ("::", '∷'), ("?1", '∼'), ("CG", '∾'), ("?-", '≃'), ("?=", '≅'), ("?2", '≈'), ("=?", '≌'),
+    ("HI", '≓'), ("!=", '≠'), ("=3", '≡'), ("=<", '≤'), (">=", '≥'), ("<*", '≪'), ("*>", '≫'),
+    ("!<", '≮'), ("!>", '≯'), ("(C", '⊂'), (")C", '⊃'), ("(_", '⊆'), (")_", '⊇'), ("0.", '⊙'),
+    ("02", '⊚'), ("-T", '⊥'), ("OK", '✓'), ("XX", '✗'), ("cH", '♥'), ("cD", '♦'), ("cS", '♠'),
+    ("cC", '♣'), ("Md", '♩'), ("M8", '♪'), ("M2", '♫'), ("Mb", '♭'), ("Mx", '♮'), ("MX", '♯'),
+    ("*1", '☆'), ("*2", '★'), ("SU", '☼'), ("hh", '─'), ("vv", '│'), ("dr", '┌'), ("dl", '┐'),
+    ("ur", '└'), ("ul", '┘'), ("vr", '├'), ("vl", '┤'), ("dh", '┬'), ("uh", '┴'), ("vh", '┼'),
+    ("FB", '█'), ("OS", '□'), ("fS", '■'), ("Db", '◆'), ("0m", '○'), ("0M", '●'), ("A=", 'А'),
+    ("B=", 'Б'), ("V=", 'В'), ("G=", 'Г'), ("D=", 'Д'), ("E=", 'Е'), ("Z%", 'Ж'), ("Z=", 'З'),
+    ("I=", 'И'), ("J=", 'Й'), ("K=", 'К'), ("L=", 'Л'), ("M=", 'М'), ("N=", 'Н'), ("O=", 'О'),
+    ("P=", 'П'), ("R=", 'Р'), ("S=", 'С'), ("T=", 'Т'), ("U=", 'У'), ("F=", 'Ф'), ("H=", 'Х'),
+    ("C=", 'Ц'), ("C%", 'Ч'), ("S%", 'Ш'), ("Sc", 'Щ'), ("=\"", 'Ъ'), ("Y=", 'Ы'),
+    ("%\"", 'Ь'), ("JE", 'Э'), ("JU", 'Ю'), ("JA", 'Я'), ("a=", 'а'), ("b=", 'б'), ("v=", 'в'),
+    ("g=", 'г'), ("d=", 'д'), ("e=", 'е'), ("z%", 'ж'), ("z=", 'з'), ("i=", 'и'), ("j=", 'й'),
+    ("k=", 'к'), ("l=", 'л'), ("m=", 'м'), ("n=", 'н'), ("o=", 'о'), ("p=", 'п'), ("r=", 'р'),
+    ("s=", 'с'), ("t=", 'т'), ("u=", 'у'), ("f=", 'ф'), ("h=", 'х'), ("c=", 'ц'), ("c%", 'ч'),
+    ("s%", 'ш'), ("sc", 'щ'), ("='", 'ъ'), ("y=", 'ы'), ("%'", 'ь'), ("je", 'э'), ("ju", 'ю'),
+    ("ja", 'я'), ("IO", 'Ё'), ("io", 'ё'),
+];
+
+/// Char for the digraph `a` `b`. User digraphs from `:digraphs` win over the table and the
+/// chars may be typed in either order.
+pub fn lookup(user: &BTreeMap<String, char>, a: char, b: char) -> Option<char> {
+    let find = |key: &str| user.get(key).copied().or_else(|| DIGRAPHS.iter().find(|(k, _)| *k == key).map(|(_, c)| *c));
+    find(&format!("{}{}", a, b)).or_else(|| find(&format!("{}{}", b, a)))
+}
+
+/// Every digraph like `a: ä 228`, user digraphs first.
+pub fn list(user: &BTreeMap<String, char>) -> Vec<String> {
+    user.iter()
+        .map(|(k, c)| (k.as_str(), *c))
+        .chain(DIGRAPHS.iter().copied())
+        .map(|(k, c)| format!("{} {} {}", k, c, c as u32))
+        .collect()
+}
+
+/// Parses the arguments of `:digraphs` like `e: 235 a* 945` into digraphs and their chars.
+pub fn parse_definitions(args: &str) -> Option<Vec<(String, char)>> {
+    let words = args.split_whitespace().collect::<Vec<&str>>();
+    if words.len() % 2 != 0 {
+        return None;
+    }
+    words
+        .chunks(2)
+        .map(|pair| {
+            let char = pair[1].parse::<u32>().ok().and_then(char::from_u32)?;
+            Some((pair[0].to_string(), char)).filter(|(key, _)| key.chars().count() == 2)
+        })
+        .collect()
+}
+
+/// What the keys typed after `Ctrl-V` make up: a literal key or a code point in decimal,
+/// octal (`o`), hex (`x`) or Unicode (`u` and `U`).
+#[derive(Debug, PartialEq)]
+pub enum Literal {
+    /// More digits may follow.
+    Pending,
+    /// Insert this char.
+    Done(char),
+    /// Insert this char, then handle the key that ended the number as usual.
+    DoneBefore(char),
+    /// The keys do not make up a char.
+    Invalid,
+}
+
+/// Reads the chars typed after `Ctrl-V` so far.
+pub fn literal(typed: &str) -> Literal {
+    let mut chars = typed.chars();
+    let (radix, max, digits) = match chars.next() {
+        Some('u') => (16, 4, chars.as_str()),
+        Some('U') => (16, 8, chars.as_str()),
+        Some('x' | 'X') => (16, 2, chars.as_str()),
+        Some('o' | 'O') => (8, 3, chars.as_str()),
+        Some(c) if c.is_ascii_digit() => (10, 3, typed),
+        Some(c) if typed.chars().count() == 1 => return Literal::Done(c),
+        _ => return Literal::Invalid,
+    };
+    let valid = digits.chars().take_while(|c| c.is_digit(radix)).count();
+    let value = |digits: &str| u32::from_str_radix(digits, radix).ok().and_then(char::from_u32);
+    if valid < digits.chars().count() {
+        // A key that is not a digit ends the number early.
+        return match value(&digits[..valid]) {
+            Some(c) if valid > 0 => Literal::DoneBefore(c),
+            _ => Literal::Invalid,
+        };
+    }
+    match valid {
+        n if n == max => value(digits).map_or(Literal::Invalid, Literal::Done),
+        _ => Literal::Pending,
+    }
+}
+
+#[test]
+fn test_digraph_lookup() {
+    let mut user = BTreeMap::new();
+    assert_eq!(lookup(&user, 'a', ':'), Some('ä'));
+    assert_eq!(lookup(&user, ':', 'a'), Some('ä'));
+    assert_eq!(lookup(&user, 'p', '*'), Some('π'));
+    assert_eq!(lookup(&user, 'E', 'u'), Some('€'));
+    user.insert("a:".to_string(), 'x');
+    assert_eq!(lookup(&user, 'a', ':'), Some('x'));
+    assert_eq!(parse_definitions("xx 8800 yy 945"), Some(vec![("xx".into(), '≠'), ("yy".into(), 'α')]));
+    assert_eq!(parse_definitions("xx"), None);
+}
+
+#[test]
+fn test_literal_code_points() {
+    assert_eq!(literal("u"), Literal::Pending);
+    assert_eq!(literal("u00e9"), Literal::Done('é'));
+    assert_eq!(literal("u3b1 "), Literal::DoneBefore('α'));
+    assert_eq!(literal("065"), Literal::Done('A'));
+    assert_eq!(literal("x41"), Literal::Done('A'));
+    assert_eq!(literal("\t"), Literal::Done('\t'));
+    assert_eq!(literal("ug"), Literal::Invalid);
+}
@@ -0,0 +1,251 @@
+use ropey::Rope;
+
+/// Chars that make up a keyword, like the words abbreviations and completion work on.
+pub fn is_keyword(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Byte offset where `Ctrl-W` stops deleting back from the end of `text`: the start of the
+/// last word, or of the last run of other non blank chars, and the blanks after it.
+pub fn word_start_before(text: &str) -> usize {
+    let trimmed = text.trim_end();
+    let last = match trimmed.chars().next_back() {
+        Some(c) => c,
+        None => return 0,
+    };
+    let keyword = is_keyword(last);
+    let word = trimmed
+        .chars()
+        .rev()
+        .take_while(|c| !c.is_whitespace() && is_keyword(*c) == keyword)
+        .map(char::len_utf8)
+        .sum::<usize>();
+    trimmed.len() - word
+}
+
+/// Index of the last line that holds text, a trailing line ending does not start a new one.
+pub fn last_line(rope: &Rope) -> usize {
+    let len = rope.len_chars();
+    if len > 0 && rope.char(len - 1) == '\n' {
+        rope.len_lines().saturating_sub(2)
+    } else {
+        rope.len_lines().saturating_sub(1)
+    }
+}
+
+/// Number of chars on `line` not counting its line ending (`\n`, `\r\n` or `\r`).
+pub fn line_len(rope: &Rope, line: usize) -> usize {
+    if line >= rope.len_lines() {
+        return 0;
+    }
+    let slice = rope.line(line);
+    let mut len = slice.len_chars();
+    if len > 0 && slice.char(len - 1) == '\n' {
+        len -= 1;
+    }
+    if len > 0 && slice.char(len - 1) == '\r' {
+        len -= 1;
+    }
+    len
+}
+
+/// Text of `line` without its line ending.
+pub fn line_text(rope: &Rope, line: usize) -> String {
+    if line >= rope.len_lines() {
+        return String::new();
+    }
+    rope.line(line).chars().take(line_len(rope, line)).collect()
+}
+
+/// Line ending used by `line`, `\n` when it has none.
+pub fn line_ending(rope: &Rope, line: usize) -> &'static str {
+    let len = if line < rope.len_lines() { rope.line(line).len_chars() } else { 0 };
+    match line_len(rope, line) {
+        l if len == l + 2 => "\r\n",
+        l if len == l + 1 && rope.line(line).char(l) == '\r' => "\r",
+        _ => "\n",
+    }
+}
+
+/// Line ending of a `fileformat`: `unix`, `dos` or `mac`.
+pub fn fileformat_ending(fileformat: &str) -> &'static str {
+    match fileformat {
+        "dos" => "\r\n",
+        "mac" => "\r",
+        _ => "\n",
+    }
+}
+
+/// `fileformat` of the line ending most lines of `rope` use, `unix` when it has none.
+pub fn detect_fileformat(rope: &Rope) -> &'static str {
+    let (mut unix, mut dos, mut mac) = (0, 0, 0);
+    for line in 0..rope.len_lines() {
+        let len = rope.line(line).len_chars();
+        match line_ending(rope, line) {
+            "\r\n" => dos += 1,
+            "\r" => mac += 1,
+            _ if len > line_len(rope, line) => unix += 1,
+            _ => {}
+        }
+    }
+    if dos > unix && dos >= mac {
+        "dos"
+    } else if mac > unix && mac > dos {
+        "mac"
+    } else {
+        "unix"
+    }
+}
+
+/// Copy of `rope` with the ending of every line replaced by `ending`, a last line without
+/// one stays without.
+pub fn with_line_endings(rope: &Rope, ending: &str) -> Rope {
+    let mut text = String::with_capacity(rope.len_bytes());
+    for line in 0..rope.len_lines() {
+        let len = line_len(rope, line);
+        text.extend(rope.line(line).chars().take(len));
+        if rope.line(line).len_chars() > len {
+            text.push_str(ending);
+        }
+    }
+    Rope::from_str(&text)
+}
+
+/// Replaces `first..=last` with `lines`, joined by the line ending of `first`.
+pub fn replace_lines(rope: &mut Rope, first: usize, last: usize, lines: &[String]) {
+    let ending = line_ending(rope, first);
+    let start = rope.line_to_char(first);
+    let end = rope.line_to_char(last) + line_len(rope, last);
+    rope.remove(start..end);
+    rope.insert(start, &lines.join(ending));
+}
+
+/// First and last line of the paragraph at `line`, the `ip` and `ap` text objects. A run of
+/// blank lines counts as a paragraph too and `around` adds the run of the other kind after it.
+pub fn paragraph(rope: &Rope, line: usize, around: bool) -> (usize, usize) {
+    let blank = |line| line_text(rope, line).trim().is_empty();
+    let last = last_line(rope);
+    let kind = blank(line);
+    let mut start = line;
+    while start > 0 && blank(start - 1) == kind {
+        start -= 1;
+    }
+    let mut end = line;
+    while end < last && blank(end + 1) == kind {
+        end += 1;
+    }
+    while around && end < last && blank(end + 1) != kind {
+        end += 1;
+    }
+    (start, end)
+}
+
+/// Char index of the next match of `pattern` after `from`, or the last one before it when
+/// searching backwards. The search wraps around the end of the text.
+pub fn find(rope: &Rope, pattern: &str, from: usize, forward: bool) -> Option<usize> {
+    if pattern.is_empty() {
+        return None;
+    }
+    let text = rope.to_string();
+    let from = rope.char_to_byte(from.min(rope.len_chars()));
+    let found = if forward {
+        let start = (from + 1..=text.len()).find(|i| text.is_char_boundary(*i)).unwrap_or(text.len());
+        text[start..].find(pattern).map(|i| i + start).or_else(|| text.find(pattern))
+    } else {
+        text[..from].rfind(pattern).or_else(|| text.rfind(pattern))
+    };
+    found.map(|byte| rope.byte_to_char(byte))
+}
+
+/// Replaces the text of `line` keeping its line ending.
+pub fn replace_line(rope: &mut Rope, line: usize, text: &str) {
+    let start = rope.line_to_char(line);
+    rope.remove(start..start + line_len(rope, line));
+    rope.insert(start, text);
+}
+
+/// Joins `count` lines starting at `line` into one, like `J` when `spaces` is true and
+/// `gJ` when it is false. Returns the column of the last join point or `None` when
+/// there was no line below to join.
+pub fn join_lines(rope: &mut Rope, line: usize, count: usize, spaces: bool) -> Option<usize> {
+    let mut col = None;
+    for _ in 1..count.max(2) {
+        if line >= last_line(rope) {
+            break;
+        }
+        let start = rope.line_to_char(line);
+        let end = start + line_len(rope, line);
+        let next = rope.line_to_char(line + 1);
+        if !spaces {
+            rope.remove(end..next);
+            col = Some(end - start);
+            continue;
+        }
+
+        let current = line_text(rope, line);
+        let next_text = line_text(rope, line + 1);
+        let indent = next_text.chars().take_while(|c| c == &' ' || c == &'\t').count();
+        let rest = &next_text[indent..];
+        rope.remove(end..next + indent);
+        let space = !(current.is_empty()
+            || current.ends_with(' ')
+            || current.ends_with('\t')
+            || rest.is_empty()
+            || rest.starts_with(')'));
+        if space {
+            rope.insert_char(end, ' ');
+        }
+        col = Some(end - start);
+    }
+    col
+}
+
+#[test]
+fn test_word_start_before() {
+    assert_eq!(word_start_before("set tw=80  "), 7);
+    assert_eq!(word_start_before("set tw="), 6);
+    assert_eq!(word_start_before("set"), 0);
+    assert_eq!(word_start_before("   "), 0);
+}
+
+#[test]
+fn test_find_wraps() {
+    let rope = Rope::from_str("one two one\ntwo");
+    assert_eq!(find(&rope, "two", 4, true), Some(12));
+    assert_eq!(find(&rope, "two", 12, true), Some(4));
+    assert_eq!(find(&rope, "one", 8, false), Some(0));
+    assert_eq!(find(&rope, "one", 0, false), Some(8));
+    assert_eq!(find(&rope, "three", 0, true), None);
+}
+
+#[test]
+fn test_join_lines_spaces() {
+    let mut rope = Rope::from_str("fn main(\n    a,\n    b\n)\n");
+    assert_eq!(join_lines(&mut rope, 0, 4, true), Some(13));
+    assert_eq!(rope.to_string(), "fn main( a, b)\n");
+
+    let mut rope = Rope::from_str("end  \n\n    next\n");
+    join_lines(&mut rope, 0, 3, true);
+    assert_eq!(rope.to_string(), "end  next\n");
+}
+
+#[test]
+fn test_join_lines_crlf() {
+    let mut rope = Rope::from_str("In the\r\n  beginning\r\nGod\r\n");
+    assert_eq!(join_lines(&mut rope, 0, 2, true), Some(6));
+    assert_eq!(rope.to_string(), "In the beginning\r\nGod\r\n");
+    assert_eq!(join_lines(&mut rope, 0, 0, false), Some(16));
+    assert_eq!(rope.to_string(), "In the beginningGod\r\n");
+    assert_eq!(join_lines(&mut rope, 0, 2, false), None);
+}
+
+#[test]
+fn test_fileformat() {
+    let rope = Rope::from_str("one\r\ntwo\r\nthree\n");
+    assert_eq!(detect_fileformat(&rope), "dos");
+    assert_eq!(detect_fileformat(&Rope::from_str("one\rtwo\r")), "mac");
+    assert_eq!(detect_fileformat(&Rope::from_str("one")), "unix");
+    assert_eq!(with_line_endings(&rope, "\n").to_string(), "one\ntwo\nthree\n");
+    let rope = Rope::from_str("one\ntwo");
+    assert_eq!(with_line_endings(&rope, fileformat_ending("dos")).to_string(), "one\r\ntwo");
+}
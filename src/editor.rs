@@ -1,5 +1,24 @@
-use crate::{screen_size, ScreenVector};
+use crate::{
+    abbrev::Abbreviations,
+    buffer::{self, Buffer},
+    complete::Completion,
+    filetype,
+    hex::HexView,
+    history::{CmdWindow, History},
+    largefile::Loader,
+    options::Options,
+    screen_size,
+    spell::Speller,
+    swap::{self, Swap},
+    undo::{self, State, Undo},
+    util::expand_home,
+    tab::TabPage,
+    window::{self, Layout, Rect, Window},
+    ScreenVector,
+};
+use crossterm::event::KeyEvent;
 use ropey::Rope;
+use std::collections::BTreeMap;
 use std::fmt::{self, Display};
 
 #[derive(Debug, PartialEq)]
@@ -7,6 +26,7 @@ pub enum Mode {
     Insert,
     Normal,
     Command,
+    Visual,
 }
 
 impl fmt::Display for Mode {
@@ -15,6 +35,7 @@ impl fmt::Display for Mode {
             Self::Insert => "Insert",
             Self::Normal => "Normal",
             Self::Command => "Command",
+            Self::Visual => "Visual",
         };
         write!(f, "{}", mode)
     }
@@ -24,52 +45,669 @@ impl fmt::Display for Mode {
 pub struct Editor {
     pub rope: Rope,
     pub file_path: Option<String>,
+    /// Viewport of the window being edited.
     pub screen: ScreenVector,
+    /// Part of the terminal the windows share, without their status lines.
+    pub area: ScreenVector,
     pub is_running: bool,
     pub mode: Mode,
     pub cursor: Cursor,
     pub command: String,
     pub output: String,
     pub error: EditorError,
+    /// Keys typed so far of a multi key mapping like `gJ`.
+    pub keys: Vec<KeyEvent>,
+    /// Count typed before a command, 0 when none was given.
+    pub count: usize,
+    /// Where visual mode was started as (column, line).
+    pub anchor: (usize, usize),
+    /// Where Insert mode was started as (column, line), `Ctrl-U` deletes back to it.
+    pub insert_start: (usize, usize),
+    /// `Ctrl-O` in Insert mode returns to it after the next Normal mode command.
+    pub insert_after: bool,
+    /// Visual mode started with `V` selects whole lines.
+    pub linewise: bool,
+    pub options: Options,
+    pub abbreviations: Abbreviations,
+    /// Abbreviations defined with `<buffer>` for the file being edited.
+    pub local_abbreviations: Abbreviations,
+    /// Insert mode completion while its popup menu is open.
+    pub completion: Option<Completion>,
+    /// Tab completion of the command line, the column is a byte offset into `command`.
+    pub wildmenu: Option<Completion>,
+    /// Text kept in registers by name, `%` is the file name and `:` the last command.
+    pub registers: BTreeMap<char, String>,
+    /// Byte offset of the cursor in `command`.
+    pub command_cursor: usize,
+    /// What the command line is for, `:` for commands and `/` or `?` for searches.
+    pub prompt: char,
+    pub command_history: History,
+    pub search_history: History,
+    /// The last search went up through the file.
+    pub search_backward: bool,
+    /// History window opened with `q:` or `q/`.
+    pub cmdwin: Option<CmdWindow>,
+    /// `Ctrl-K`, `Ctrl-V` or `z=` waiting for more keys.
+    pub pending: Option<Pending>,
+    /// Digraphs defined with `:digraphs`.
+    pub digraphs: BTreeMap<String, char>,
+    /// Dictionary loaded when `spell` is turned on.
+    pub speller: Option<Speller>,
+    /// The text was changed since it was read or written.
+    pub modified: bool,
+    /// Opened read-only, writing needs `!`.
+    pub readonly: bool,
+    /// Changes since the swap file was written.
+    pub changes: usize,
+    pub undo: Undo,
+    /// Edits made with `rope_mut`, compared before and after every key to tell whether it
+    /// changed the buffer without comparing the whole text.
+    pub edits: u64,
+    /// The file was opened in large file mode, see `Options::largefile`.
+    pub largefile: bool,
+    /// Files of large file mode still being read, the first one goes on when no key is typed.
+    pub loaders: Vec<Loader>,
+    /// The buffer is shown as hex bytes instead of text, `:hex` toggles it.
+    pub hex: Option<HexView>,
+    /// Every buffer in the order `:ls` shows them, including the one being edited.
+    pub buffers: Vec<Buffer>,
+    /// Number of the buffer being edited.
+    pub buffer: usize,
+    /// Number of the buffer edited before this one, `Ctrl-^` goes back to it.
+    pub alternate: Option<usize>,
+    /// Every window including the one being edited.
+    pub windows: Vec<Window>,
+    /// Id of the window being edited.
+    pub window: usize,
+    pub layout: Layout,
+    /// Every tab page including the one shown, which keeps its windows in the fields above.
+    pub tabs: Vec<TabPage>,
+    /// Index of the tab page shown.
+    pub tab: usize,
 }
 
 impl Editor {
     pub fn new(rope: Rope, file_path: Option<String>) -> Self {
+        let filetype = file_path.as_deref().map(filetype::detect).unwrap_or_default();
+        let options = Options {
+            pairs: filetype::pairs(&filetype).to_string(),
+            filetype,
+            fileformat: crate::edit::detect_fileformat(&rope).to_string(),
+            ..Options::default()
+        };
         Self {
             rope,
             file_path,
-            screen: screen_size(),
+            screen: screen_size(false),
+            area: screen_size(false),
             is_running: true,
             mode: Mode::Normal,
             cursor: Cursor::default(),
             command: String::new(),
             output: String::new(),
             error: EditorError::NONE,
+            keys: Vec::new(),
+            count: 0,
+            anchor: (0, 0),
+            insert_start: (0, 0),
+            insert_after: false,
+            linewise: false,
+            options,
+            abbreviations: Abbreviations::default(),
+            local_abbreviations: Abbreviations::default(),
+            completion: None,
+            wildmenu: None,
+            registers: BTreeMap::new(),
+            command_cursor: 0,
+            prompt: ':',
+            command_history: History::default(),
+            search_history: History::default(),
+            search_backward: false,
+            cmdwin: None,
+            pending: None,
+            digraphs: BTreeMap::new(),
+            speller: None,
+            modified: false,
+            readonly: false,
+            changes: 0,
+            undo: Undo::default(),
+            edits: 0,
+            largefile: false,
+            loaders: Vec::new(),
+            hex: None,
+            buffers: vec![Buffer { number: 1, ..Buffer::default() }],
+            buffer: 1,
+            alternate: None,
+            windows: vec![Window { id: 1, buffer: 1, ..Window::default() }],
+            window: 1,
+            layout: Layout::Window(1),
+            tabs: vec![TabPage::default()],
+            tab: 0,
+        }
+    }
+
+    /// Count given to the current command or `default` if there was none.
+    pub fn count_or(&self, default: usize) -> usize {
+        if self.count == 0 {
+            default
+        } else {
+            self.count
+        }
+    }
+
+    /// Index of the last line that holds text.
+    pub fn last_line(&self) -> usize {
+        crate::edit::last_line(&self.rope)
+    }
+
+    /// Contents of register `name`.
+    pub fn register(&self, name: char) -> Option<String> {
+        match name {
+            '%' => self.file_path.clone(),
+            '#' => self.alternate.and_then(|number| self.buffers.iter().find(|b| b.number == number)?.file_path.clone()),
+            name => self.registers.get(&name).cloned(),
+        }
+    }
+
+    /// Names of the registers that hold text.
+    pub fn register_names(&self) -> Vec<String> {
+        let mut names = self.registers.keys().map(|name| name.to_string()).collect::<Vec<String>>();
+        if self.file_path.is_some() {
+            names.push('%'.to_string());
+        }
+        if self.register('#').is_some() {
+            names.push('#'.to_string());
+        }
+        names.sort();
+        names
+    }
+
+    /// First and last line of the visual selection.
+    pub fn visual_lines(&self) -> (usize, usize) {
+        let (a, b) = (self.anchor.1, self.cursor.gy as usize);
+        (a.min(b), a.max(b))
+    }
+
+    /// History of the kind of line the command line is used for.
+    pub fn history_mut(&mut self, prompt: char) -> &mut History {
+        if prompt == ':' {
+            &mut self.command_history
+        } else {
+            &mut self.search_history
+        }
+    }
+
+    /// Rope char range covered by the visual selection, the cursor char is included.
+    pub fn visual_range(&self) -> std::ops::Range<usize> {
+        if self.linewise {
+            let (start, end) = self.visual_lines();
+            return self.rope.line_to_char(start)
+                ..self.rope.line_to_char(end) + crate::edit::line_len(&self.rope, end);
+        }
+        let anchor = self.rope.line_to_char(self.anchor.1) + self.anchor.0;
+        let cursor = self.rope.line_to_char(self.cursor.gy as usize) + self.cursor.gx;
+        let end = (anchor.max(cursor) + 1).min(self.rope.len_chars());
+        anchor.min(cursor)..end
+    }
+
+    /// Moves the cursor to `col` of `line`, scrolling the screen so it stays visible.
+    pub fn goto(&mut self, line: usize, col: usize) {
+        let line = line.min(self.last_line());
+        let col = col.min(crate::edit::line_len(&self.rope, line));
+        if line < self.screen.t {
+            self.screen.t = line;
+        } else if line >= self.screen.t + self.screen.max_h {
+            self.screen.t = line + 1 - self.screen.max_h;
+        }
+        self.cursor.gy = line as u32;
+        self.cursor.y = (line - self.screen.t) as u16;
+        self.cursor.gx = col;
+        self.cursor.x = col as u16;
+        self.cursor.max_x = col;
+    }
+
+    /// The text to edit, counting the edit in `edits`.
+    pub fn rope_mut(&mut self) -> &mut Rope {
+        self.edits += 1;
+        &mut self.rope
+    }
+
+    /// Text and cursor as undo keeps them.
+    pub fn state(&self) -> State {
+        State { rope: self.rope.clone(), cursor: (self.cursor.gx, self.cursor.gy as usize) }
+    }
+
+    /// Puts back text and cursor kept by undo, the buffer is unmodified again when that is
+    /// the text last written.
+    pub fn restore(&mut self, state: State) {
+        self.rope = state.rope;
+        self.modified = !self.undo.is_saved();
+        self.goto(state.cursor.1, state.cursor.0);
+    }
+
+    fn buffer_index(&self) -> usize {
+        self.buffers.iter().position(|b| b.number == self.buffer).unwrap_or(0)
+    }
+
+    /// Copies what other commands look up about the buffer being edited to its list entry.
+    fn sync_buffer(&mut self) {
+        let index = self.buffer_index();
+        self.buffers[index].file_path = self.file_path.clone();
+        self.buffers[index].modified = self.modified;
+        self.buffers[index].cursor = (self.cursor.gx, self.cursor.gy as usize);
+    }
+
+    /// Number of the buffer `arg` names, see `buffer::find`.
+    pub fn find_buffer(&mut self, arg: &str) -> Result<usize, EditorError> {
+        self.sync_buffer();
+        buffer::find(&self.buffers, arg)
+    }
+
+    /// Name of the buffer being edited when it has unsaved changes.
+    pub fn modified_name(&self) -> Option<String> {
+        self.modified.then(|| self.file_path.clone().unwrap_or_else(|| "[No Name]".to_string()))
+    }
+
+    /// Adds a buffer for `file_path` to the end of the list and returns its number.
+    pub fn add_buffer(&mut self, rope: Rope, file_path: Option<String>) -> usize {
+        let number = self.buffers.iter().map(|b| b.number).max().unwrap_or(0) + 1;
+        let filetype = file_path.as_deref().map(filetype::detect).unwrap_or_default();
+        self.buffers.push(Buffer {
+            number,
+            fileformat: crate::edit::detect_fileformat(&rope).to_string(),
+            fileencoding: "utf-8".to_string(),
+            rope,
+            file_path,
+            pairs: filetype::pairs(&filetype).to_string(),
+            filetype,
+            ..Buffer::default()
+        });
+        number
+    }
+
+    /// Makes buffer `number` the one being edited, the one edited so far becomes the alternate.
+    pub fn switch_buffer(&mut self, number: usize) {
+        let index = match self.buffers.iter().position(|b| b.number == number) {
+            Some(index) if number != self.buffer => index,
+            _ => return,
+        };
+        if self.changes > 0 {
+            self.write_swap();
+        }
+        self.sync_buffer();
+        let current = self.buffer_index();
+        let old = Buffer {
+            number: self.buffer,
+            rope: std::mem::take(&mut self.rope),
+            file_path: self.file_path.take(),
+            cursor: (self.cursor.gx, self.cursor.gy as usize),
+            top: self.screen.t,
+            modified: self.modified,
+            readonly: self.readonly,
+            undo: std::mem::take(&mut self.undo),
+            filetype: std::mem::take(&mut self.options.filetype),
+            fileformat: std::mem::take(&mut self.options.fileformat),
+            fileencoding: std::mem::take(&mut self.options.fileencoding),
+            pairs: std::mem::take(&mut self.options.pairs),
+            local_abbreviations: std::mem::take(&mut self.local_abbreviations),
+            largefile: self.largefile,
+            hex: self.hex.take(),
+        };
+        self.buffers[current] = old;
+        let new = std::mem::replace(&mut self.buffers[index], Buffer { number, ..Buffer::default() });
+        self.alternate = Some(self.buffer);
+        self.buffer = number;
+        self.rope = new.rope;
+        self.file_path = new.file_path;
+        self.modified = new.modified;
+        self.readonly = new.readonly;
+        self.undo = new.undo;
+        self.options.filetype = new.filetype;
+        self.options.fileformat = new.fileformat;
+        self.options.fileencoding = new.fileencoding;
+        self.options.pairs = new.pairs;
+        self.local_abbreviations = new.local_abbreviations;
+        self.largefile = new.largefile;
+        self.hex = new.hex;
+        self.screen.t = new.top;
+        self.goto(new.cursor.1, new.cursor.0);
+        self.sync_buffer();
+    }
+
+    /// Saves the text of the buffer being edited to its swap file.
+    pub fn write_swap(&mut self) {
+        self.changes = 0;
+        if let Some(path) = self.file_path.as_deref().filter(|_| self.options.swapfile && self.modified && !self.largefile) {
+            if let Err(error) = swap::write(path, &self.rope) {
+                self.error = error;
+            }
+        }
+    }
+
+    /// Reads the undo history kept for the file of the buffer being edited with `undofile`.
+    pub fn load_undo(&mut self) {
+        let path = match self.file_path.as_deref() {
+            Some(path) if self.options.undofile && self.undo.is_empty() && !self.largefile => path,
+            _ => return,
+        };
+        let undo_path = undo::undo_path(&expand_home(&self.options.undodir), path);
+        match Undo::load(&self.rope, &undo_path) {
+            Ok(Some(undo)) => self.undo = undo,
+            Ok(None) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => self.error = EditorError::Io(format!("{}: {}", undo_path.display(), error)),
+        }
+    }
+
+    /// Keeps the undo history of `rope` written to `path` when `undofile` is on.
+    pub fn save_undo(&self, undo: &Undo, rope: &Rope, path: &str) -> Result<(), EditorError> {
+        if !self.options.undofile {
+            return Ok(());
+        }
+        let undo_path = undo::undo_path(&expand_home(&self.options.undodir), path);
+        undo.save(rope, &undo_path).map_err(|error| EditorError::Io(format!("{}: {}", undo_path.display(), error)))
+    }
+
+    /// Reads the rest of buffer `number` with `loader` while keys are handled, the buffer is in
+    /// large file mode from now on.
+    pub fn start_loading(&mut self, number: usize, mut loader: Loader) {
+        loader.buffer = number;
+        self.loaders.retain(|loader| loader.buffer != number);
+        self.loaders.push(loader);
+        match self.buffers.iter_mut().find(|b| b.number == number) {
+            _ if number == self.buffer => self.largefile = true,
+            Some(buffer) => buffer.largefile = true,
+            None => {}
+        }
+    }
+
+    /// Adds the next chunk of the first file still being read to its buffer.
+    pub fn load_chunk(&mut self) {
+        if self.loaders.is_empty() {
+            return;
+        }
+        let mut loader = self.loaders.remove(0);
+        let text = match loader.next_chunk() {
+            Ok(Some(text)) => text,
+            Ok(None) => return,
+            Err(error) => return self.error = EditorError::Io(error.to_string()),
+        };
+        let (rope, readonly, path) = match self.buffers.iter_mut().find(|b| b.number == loader.buffer) {
+            _ if loader.buffer == self.buffer => (&mut self.rope, &mut self.readonly, &self.file_path),
+            Some(buffer) => (&mut buffer.rope, &mut buffer.readonly, &buffer.file_path),
+            None => return,
+        };
+        rope.insert(rope.len_chars(), &text);
+        // Writing the replacement chars back would change the file, so that takes a `!`.
+        if std::mem::take(&mut loader.replaced) {
+            *readonly = true;
+            self.error = EditorError::IllegalByte(path.clone().unwrap_or_default());
+        }
+        self.loaders.insert(0, loader);
+    }
+
+    /// Reads what is left of buffer `number`, before it is written.
+    pub fn finish_loading(&mut self, number: usize) {
+        while let Some(index) = self.loaders.iter().position(|loader| loader.buffer == number) {
+            let loader = self.loaders.remove(index);
+            self.loaders.insert(0, loader);
+            self.load_chunk();
         }
     }
+
+    /// Percentage of the buffer being edited read so far, while it is still being read.
+    pub fn loading(&self) -> Option<u64> {
+        self.loaders.iter().find(|loader| loader.buffer == self.buffer).map(Loader::progress)
+    }
+
+    /// Deletes the swap files of every buffer, when leaving the editor.
+    pub fn remove_swaps(&self) {
+        let paths = self.buffers.iter().map(|b| b.file_path.as_deref()).chain([self.file_path.as_deref()]);
+        for path in paths.flatten() {
+            swap::remove(path);
+        }
+    }
+
+    /// Removes buffer `number` from the list. In place of the buffer being edited comes the
+    /// alternate one, the next one or a new empty one.
+    pub fn delete_buffer(&mut self, number: usize) {
+        if number == self.buffer {
+            let others = self.buffers.iter().map(|b| b.number).filter(|n| *n != number).collect::<Vec<usize>>();
+            let next = self
+                .alternate
+                .filter(|n| others.contains(n))
+                .or_else(|| others.iter().find(|n| **n > number).or(others.last()).copied());
+            let next = match next {
+                Some(next) => next,
+                None => self.add_buffer(Rope::new(), None),
+            };
+            self.switch_buffer(next);
+        }
+        if let Some(path) = self.buffers.iter().find(|b| b.number == number).and_then(|b| b.file_path.as_deref()) {
+            swap::remove(path);
+        }
+        self.buffers.retain(|b| b.number != number);
+        if self.alternate == Some(number) {
+            self.alternate = None;
+        }
+        let tabs = self.tabs.iter_mut().flat_map(|tab| tab.windows.iter_mut());
+        for window in self.windows.iter_mut().chain(tabs).filter(|w| w.buffer == number) {
+            window.buffer = self.buffer;
+        }
+    }
+
+    /// Buffer `number` when it is not the one being edited.
+    pub fn other_buffer(&self, number: usize) -> Option<&Buffer> {
+        self.buffers.iter().find(|b| b.number == number && number != self.buffer)
+    }
+
+    /// Screen the windows are laid out on, including their status lines.
+    pub fn window_area(&self) -> Rect {
+        Rect { x: self.area.l, y: self.area.b, w: self.area.max_w, h: self.area.max_h + 1 }
+    }
+
+    /// Every window with the part of the screen it covers.
+    pub fn window_rects(&self) -> Vec<(usize, Rect)> {
+        self.layout.rects(self.window_area())
+    }
+
+    /// Copies the cursor and viewport of the window being edited to its entry.
+    fn sync_window(&mut self) {
+        let (id, buffer, top) = (self.window, self.buffer, self.screen.t);
+        let cursor = (self.cursor.gx, self.cursor.gy as usize);
+        if let Some(window) = self.windows.iter_mut().find(|w| w.id == id) {
+            *window = Window { id, buffer, cursor, top };
+        }
+    }
+
+    /// Sizes the viewport to the part of the screen the window being edited has now.
+    pub fn fit_window(&mut self) {
+        let rect = self.window_rects().into_iter().find(|(id, _)| *id == self.window).map(|(_, rect)| rect);
+        let rect = rect.unwrap_or_else(|| self.window_area());
+        self.screen.l = rect.x;
+        self.screen.b = rect.y;
+        self.screen.max_w = rect.w;
+        self.screen.max_h = rect.h.saturating_sub(1).max(1);
+        self.goto(self.cursor.gy as usize, self.cursor.gx);
+    }
+
+    /// Makes window `id` the one being edited.
+    pub fn enter_window(&mut self, id: usize) {
+        if self.windows.iter().any(|w| w.id == id) {
+            self.sync_window();
+            self.load_window(id);
+        }
+    }
+
+    /// Takes the cursor, viewport and buffer of window `id` into the editor.
+    fn load_window(&mut self, id: usize) {
+        let window = self.windows.iter().find(|w| w.id == id).cloned().unwrap_or_default();
+        self.window = id;
+        if window.buffer != self.buffer {
+            let alternate = self.alternate;
+            self.switch_buffer(window.buffer);
+            self.alternate = alternate;
+        }
+        self.screen.t = window.top;
+        self.cursor.gy = window.cursor.1 as u32;
+        self.cursor.gx = window.cursor.0;
+        self.fit_window();
+    }
+
+    /// `:split` and `:vsplit`, opens a second window on the buffer above or left of this one.
+    pub fn split_window(&mut self, vertical: bool) {
+        self.sync_window();
+        let id = self.windows.iter().map(|w| w.id).max().unwrap_or(0) + 1;
+        let size = match vertical {
+            true => self.screen.max_w / 2,
+            false => (self.screen.max_h + 1).div_ceil(2),
+        };
+        self.layout.split(self.window, id, vertical, size);
+        let window = self.windows.iter().find(|w| w.id == self.window).cloned().unwrap_or_default();
+        self.windows.push(Window { id, ..window });
+        self.window = id;
+        self.fit_window();
+    }
+
+    /// `:close`, the window that gets the space is entered when it was the one being edited.
+    pub fn close_window(&mut self, id: usize) -> Result<(), EditorError> {
+        if self.windows.len() == 1 {
+            return Err(EditorError::LastWindow);
+        }
+        let rects = self.window_rects();
+        let next = "kjhl".chars().find_map(|direction| window::neighbour(&rects, id, direction, (0, 0)));
+        self.layout.remove(id);
+        self.windows.retain(|w| w.id != id);
+        if id == self.window {
+            self.enter_window(next.unwrap_or(self.windows[0].id));
+        }
+        self.fit_window();
+        Ok(())
+    }
+
+    /// `:only`, closes every window but the one being edited.
+    pub fn only_window(&mut self) {
+        self.layout = Layout::Window(self.window);
+        let id = self.window;
+        self.windows.retain(|w| w.id == id);
+        self.fit_window();
+    }
+
+    /// Makes room for the tabline when there is more than one tab page, or takes it back.
+    fn fit_area(&mut self) {
+        self.area = screen_size(self.tabs.len() > 1);
+        self.fit_window();
+    }
+
+    /// Shows tab page `index`.
+    pub fn switch_tab(&mut self, index: usize) {
+        if index == self.tab || index >= self.tabs.len() {
+            return;
+        }
+        self.sync_window();
+        let shown = TabPage {
+            windows: std::mem::take(&mut self.windows),
+            window: self.window,
+            layout: std::mem::replace(&mut self.layout, Layout::Window(0)),
+        };
+        self.tabs[self.tab] = shown;
+        let tab = std::mem::take(&mut self.tabs[index]);
+        self.tab = index;
+        self.windows = tab.windows;
+        self.layout = tab.layout;
+        self.load_window(tab.window);
+    }
+
+    /// `:tabnew`, opens a tab page after this one with a window on the buffer being edited.
+    pub fn new_tab(&mut self) {
+        self.sync_window();
+        let window = self.windows.iter().find(|w| w.id == self.window).cloned().unwrap_or_default();
+        self.tabs.insert(self.tab + 1, TabPage::new(Window { id: 1, ..window }));
+        self.fit_area();
+        self.switch_tab(self.tab + 1);
+    }
+
+    /// `:tabclose`, when the tab page shown is closed the one after it or else before it is shown.
+    pub fn close_tab(&mut self, index: usize) -> Result<(), EditorError> {
+        if self.tabs.len() == 1 {
+            return Err(EditorError::LastTabPage);
+        }
+        if index == self.tab {
+            self.switch_tab(if index + 1 < self.tabs.len() { index + 1 } else { index - 1 });
+        }
+        self.tabs.remove(index);
+        if index < self.tab {
+            self.tab -= 1;
+        }
+        self.fit_area();
+        Ok(())
+    }
+
+    /// `:tabmove`, puts the tab page shown at `index`.
+    pub fn move_tab(&mut self, index: usize) {
+        let tab = self.tabs.remove(self.tab);
+        self.tabs.insert(index, tab);
+        self.tab = index;
+    }
+
+    /// Name and modified flag of the buffer in the active window of tab page `index`.
+    pub fn tab_label(&self, index: usize) -> (String, bool) {
+        let buffer = match index == self.tab {
+            true => self.buffer,
+            false => {
+                let tab = &self.tabs[index];
+                tab.windows.iter().find(|w| w.id == tab.window).map_or(self.buffer, |w| w.buffer)
+            }
+        };
+        match self.other_buffer(buffer) {
+            Some(buffer) => (buffer.name().to_string(), buffer.modified),
+            None => (self.file_path.clone().unwrap_or_else(|| "[No Name]".to_string()), self.modified),
+        }
+    }
+
+    /// `Ctrl-W +` and friends, grows the window being edited by `delta` rows or columns.
+    pub fn resize_window(&mut self, vertical: bool, delta: isize) {
+        let area = self.window_area();
+        self.layout.resize(self.window, vertical, delta, area);
+        self.fit_window();
+    }
+
+    /// `:ls`, one line for every buffer with its number, flags, name and cursor line.
+    pub fn list_buffers(&mut self) -> String {
+        self.sync_buffer();
+        let lines = self.buffers.iter().map(|b| {
+            let flag = match b.number {
+                n if n == self.buffer => '%',
+                n if Some(n) == self.alternate => '#',
+                _ => ' ',
+            };
+            let modified = if b.modified { '+' } else { ' ' };
+            format!("{:>3} {} {} \"{}\" line {}", b.number, flag, modified, b.name(), b.cursor.1 + 1)
+        });
+        lines.collect::<Vec<String>>().join("\n")
+    }
 }
 
+/// A command waiting for the keys typed after it.
 #[derive(Debug)]
+pub enum Pending {
+    /// `Ctrl-K` and the first char of the digraph once it is typed.
+    Digraph(Option<char>),
+    /// `Ctrl-V` and the chars typed after it.
+    Literal(String),
+    /// `z=` showing suggestions, a digit picks the one replacing the word at (column, line).
+    Suggestion(Vec<String>, (usize, usize)),
+    /// A swap file was found for the file just opened, a key picks what to do with it.
+    Swap(Swap),
+}
+
+#[derive(Debug, Default)]
 pub struct Cursor {
     pub x: u16,
     pub y: u16,
-    pub gx: u16,
-    pub gy: u16,
-    pub max_x: u16,
-    pub max_y: u16,
-}
-
-impl Default for Cursor {
-    fn default() -> Self {
-        Self {
-            x: 0,
-            y: 0,
-            gx: 0,
-            gy: 0,
-            max_x: 0,
-            max_y: 0,
-        }
-    }
+    pub gx: usize,
+    pub gy: u32,
+    pub max_x: usize,
 }
 
 impl Display for Cursor {
@@ -81,6 +719,23 @@ impl Display for Cursor {
 #[derive(Debug)]
 pub enum EditorError {
     InvalidCommand(String),
+    UnknownOption(String),
+    InvalidArgument(String),
+    NoSuchAbbreviation(String),
+    PatternNotFound(String),
+    Io(String),
+    NoSuchBuffer(String),
+    MoreThanOneMatch(String),
+    NoAlternateFile,
+    NoWriteSinceLastChange(String),
+    LastWindow,
+    LastTabPage,
+    NoFileName,
+    FileExists(String),
+    WriteFailed(String),
+    ReadOnly(String),
+    IllegalByte(String),
+    #[allow(clippy::upper_case_acronyms)]
     NONE,
 }
 
@@ -88,9 +743,23 @@ impl Display for EditorError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::InvalidCommand(c) => write!(f, "Invalid Command: {}", c),
+            Self::UnknownOption(o) => write!(f, "Unknown Option: {}", o),
+            Self::InvalidArgument(a) => write!(f, "Invalid Argument: {}", a),
+            Self::NoSuchAbbreviation(a) => write!(f, "No Such Abbreviation: {}", a),
+            Self::PatternNotFound(p) => write!(f, "Pattern Not Found: {}", p),
+            Self::Io(e) => write!(f, "{}", e),
+            Self::NoSuchBuffer(b) => write!(f, "No Such Buffer: {}", b),
+            Self::MoreThanOneMatch(b) => write!(f, "More Than One Match: {}", b),
+            Self::NoAlternateFile => write!(f, "No Alternate File"),
+            Self::LastWindow => write!(f, "Cannot Close Last Window"),
+            Self::LastTabPage => write!(f, "Cannot Close Last Tab Page"),
+            Self::NoFileName => write!(f, "No File Name"),
+            Self::FileExists(p) => write!(f, "File Exists: {} (add ! to override)", p),
+            Self::WriteFailed(e) => write!(f, "Write Failed: {}", e),
+            Self::ReadOnly(p) => write!(f, "Read-Only: {} (add ! to override)", p),
+            Self::IllegalByte(p) => write!(f, "Illegal Byte: {} is read-only (add ! to write)", p),
+            Self::NoWriteSinceLastChange(b) => write!(f, "No Write Since Last Change: {} (add ! to override)", b),
             Self::NONE => write!(f, ""),
         }
     }
 }
-
-
@@ -0,0 +1,157 @@
+use std::convert::TryFrom;
+
+/// Every `fileencoding` files are read and written in. `utf-8-bom` is UTF-8 starting with a
+/// byte order mark and the UTF-16 ones always start with one.
+pub const NAMES: &[&str] = &["latin1", "utf-16be", "utf-16le", "utf-8", "utf-8-bom"];
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// Encoding of `bytes`: UTF-16 by its byte order mark, UTF-8 when it is valid UTF-8 and Latin-1,
+/// which any bytes are, when it is not. Binary files, with a NUL byte near the start, are read
+/// as Latin-1 too so every byte stays one char.
+pub fn detect(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(UTF8_BOM) && std::str::from_utf8(&bytes[UTF8_BOM.len()..]).is_ok() {
+        "utf-8-bom"
+    } else if bytes.starts_with(UTF16LE_BOM) {
+        "utf-16le"
+    } else if bytes.starts_with(UTF16BE_BOM) {
+        "utf-16be"
+    } else if bytes.iter().take(8000).any(|b| *b == 0) {
+        "latin1"
+    } else if std::str::from_utf8(bytes).is_ok() {
+        "utf-8"
+    } else {
+        "latin1"
+    }
+}
+
+/// Text of `bytes` read as `encoding` without its byte order mark. Bytes that are not valid in
+/// it become the replacement char.
+pub fn decode(bytes: &[u8], encoding: &str) -> String {
+    decode_chunk(bytes.strip_prefix(bom(encoding)).unwrap_or(bytes), encoding)
+}
+
+/// Text of a part of a file after its byte order mark, see `complete_len` for where to cut it.
+pub fn decode_chunk(bytes: &[u8], encoding: &str) -> String {
+    match encoding {
+        "latin1" => bytes.iter().map(|b| *b as char).collect(),
+        "utf-16le" | "utf-16be" => String::from_utf16_lossy(&utf16_units(bytes, encoding)),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Whether `decode_chunk` reads `bytes` as `encoding` without replacing any of them.
+pub fn is_valid(bytes: &[u8], encoding: &str) -> bool {
+    match encoding {
+        "latin1" => true,
+        "utf-16le" | "utf-16be" => bytes.len().is_multiple_of(2) && char::decode_utf16(utf16_units(bytes, encoding)).all(|c| c.is_ok()),
+        _ => std::str::from_utf8(bytes).is_ok(),
+    }
+}
+
+fn utf16_units(bytes: &[u8], encoding: &str) -> Vec<u16> {
+    bytes
+        .chunks(2)
+        .map(|pair| match (pair, encoding) {
+            ([a, b], "utf-16le") => u16::from_le_bytes([*a, *b]),
+            ([a, b], _) => u16::from_be_bytes([*a, *b]),
+            _ => 0xFFFD,
+        })
+        .collect()
+}
+
+/// Length of the start of `bytes` that does not end in the middle of a char, the rest is
+/// decoded together with the bytes read after it.
+pub fn complete_len(bytes: &[u8], encoding: &str) -> usize {
+    let len = bytes.len();
+    match encoding {
+        "latin1" => len,
+        "utf-16le" | "utf-16be" => {
+            let even = len - len % 2;
+            let last = match (bytes.get(even.saturating_sub(2)..even), encoding) {
+                (Some([a, b]), "utf-16le") => u16::from_le_bytes([*a, *b]),
+                (Some([a, b]), _) => u16::from_be_bytes([*a, *b]),
+                _ => return even,
+            };
+            if (0xD800..0xDC00).contains(&last) {
+                even - 2
+            } else {
+                even
+            }
+        }
+        _ => {
+            for back in 1..=len.min(3) {
+                let byte = bytes[len - back];
+                if byte & 0xC0 == 0x80 {
+                    continue;
+                }
+                let needed = match byte {
+                    0xF0..=0xFF => 4,
+                    0xE0..=0xEF => 3,
+                    0xC0..=0xDF => 2,
+                    _ => 1,
+                };
+                return if needed > back { len - back } else { len };
+            }
+            len
+        }
+    }
+}
+
+/// Bytes of `text` in `encoding` without the byte order mark, or the first char it cannot hold.
+pub fn encode_chunk(text: &str, encoding: &str) -> Result<Vec<u8>, char> {
+    let mut bytes = Vec::with_capacity(text.len());
+    match encoding {
+        "latin1" => {
+            for c in text.chars() {
+                bytes.push(u8::try_from(c as u32).map_err(|_| c)?);
+            }
+        }
+        "utf-16le" => bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+        "utf-16be" => bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes)),
+        _ => bytes.extend_from_slice(text.as_bytes()),
+    }
+    Ok(bytes)
+}
+
+/// Byte order mark `encoding` starts with, empty when it has none.
+pub fn bom(encoding: &str) -> &'static [u8] {
+    match encoding {
+        "utf-8-bom" => UTF8_BOM,
+        "utf-16le" => UTF16LE_BOM,
+        "utf-16be" => UTF16BE_BOM,
+        _ => &[],
+    }
+}
+
+#[test]
+fn test_detect_and_decode() {
+    let utf16 = [0xFF, 0xFE, b'h', 0, b'i', 0, 0x3D, 0xD8, 0x00, 0xDE];
+    assert_eq!(detect(&utf16), "utf-16le");
+    assert_eq!(decode(&utf16, "utf-16le"), "hi\u{1F600}");
+    assert_eq!(detect(b"\xEF\xBB\xBFok"), "utf-8-bom");
+    assert_eq!(decode(b"\xEF\xBB\xBFok", "utf-8-bom"), "ok");
+    assert_eq!(detect("caf\u{e9}".as_bytes()), "utf-8");
+    assert_eq!(detect(b"caf\xE9"), "latin1");
+    assert_eq!(decode(b"caf\xE9", "latin1"), "caf\u{e9}");
+    assert_eq!(detect(b"\x7fELF\x02\x01\x01\0"), "latin1");
+}
+
+#[test]
+fn test_encode() {
+    assert_eq!(encode_chunk("caf\u{e9}", "latin1"), Ok(b"caf\xE9".to_vec()));
+    assert_eq!(encode_chunk("\u{263A}", "latin1"), Err('\u{263A}'));
+    assert_eq!(encode_chunk("hi", "utf-16be"), Ok(vec![0, b'h', 0, b'i']));
+    assert_eq!(bom("utf-16be"), &[0xFE, 0xFF]);
+}
+
+#[test]
+fn test_complete_len() {
+    let bytes = "a\u{e9}\u{1F600}".as_bytes();
+    assert_eq!(complete_len(&bytes[..2], "utf-8"), 1);
+    assert_eq!(complete_len(&bytes[..5], "utf-8"), 3);
+    assert_eq!(complete_len(bytes, "utf-8"), 7);
+    assert_eq!(complete_len(&[b'h', 0, 0x3D, 0xD8, 0x00], "utf-16le"), 2);
+}
@@ -0,0 +1,71 @@
+use std::path::Path;
+
+/// Guesses the filetype of `path` from its extension or file name.
+pub fn detect(path: &str) -> String {
+    let path = Path::new(path);
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let filetype = match (name, extension) {
+        ("Makefile" | "makefile", _) => "make",
+        (".bashrc" | ".profile" | ".zshrc", _) => "sh",
+        (_, "rs") => "rust",
+        (_, "toml") => "toml",
+        (_, "sh" | "bash" | "zsh") => "sh",
+        (_, "md" | "markdown") => "markdown",
+        (_, "py") => "python",
+        (_, "c" | "h") => "c",
+        (_, "cpp" | "hpp" | "cc") => "cpp",
+        (_, "js" | "ts") => "javascript",
+        (_, "lua") => "lua",
+        (_, "html" | "xml") => "html",
+        (_, "vim") => "vim",
+        (_, "yaml" | "yml") => "yaml",
+        _ => "",
+    };
+    filetype.to_string()
+}
+
+/// Start and end markers of a line comment for `filetype`, the end is empty for most languages.
+pub fn comment_markers(filetype: &str) -> (&'static str, &'static str) {
+    match filetype {
+        "rust" | "c" | "cpp" | "javascript" => ("//", ""),
+        "markdown" | "html" => ("<!--", "-->"),
+        "lua" => ("--", ""),
+        "vim" => ("\"", ""),
+        _ => ("#", ""),
+    }
+}
+
+/// Comment markers `gq` and auto wrap keep at the start of every line of a wrapped comment,
+/// longest first. Text files have none, so Markdown headings and bullets stay text.
+pub fn comment_leaders(filetype: &str) -> &'static [&'static str] {
+    match filetype {
+        "rust" => &["///", "//!", "//"],
+        "c" | "cpp" | "javascript" => &["//", "*"],
+        "markdown" => &[">"],
+        "lua" => &["--"],
+        "vim" => &["\""],
+        "" | "text" | "html" => &[],
+        _ => &["#"],
+    }
+}
+
+/// Comment marker spell checking looks for in source files, `None` for text files that are
+/// checked all over.
+pub fn spell_comment(filetype: &str) -> Option<&'static str> {
+    match filetype {
+        "" | "markdown" | "text" | "html" => None,
+        filetype => Some(comment_markers(filetype).0),
+    }
+}
+
+/// Chars `autopairs` closes for `filetype`, as open and close pairs. Languages that use a
+/// single quote on its own, like Rust lifetimes, leave it out.
+pub fn pairs(filetype: &str) -> &'static str {
+    match filetype {
+        "rust" | "lisp" | "vim" => "()[]{}\"\"",
+        "markdown" => "()[]{}\"\"``",
+        "html" => "()[]{}\"\"''<>",
+        _ => "()[]{}\"\"''",
+    }
+}
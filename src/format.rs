@@ -0,0 +1,130 @@
+use crate::filetype;
+
+/// Width used when `textwidth` is 0.
+pub const DEFAULT_WIDTH: usize = 79;
+
+/// Splits a line into its leader, the indent plus the comment marker of `filetype` and the
+/// space after it, and the text that follows.
+pub fn split_leader<'a>(line: &'a str, filetype: &str) -> (&'a str, &'a str) {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    let markers = filetype::comment_leaders(filetype);
+    let marker = markers.iter().find(|m| rest.starts_with(*m)).map_or(0, |m| m.len());
+    let space = rest[marker..].len() - rest[marker..].trim_start().len();
+    let space = if marker == 0 { 0 } else { space.min(1) };
+    line.split_at(indent + marker + space)
+}
+
+/// Leaders of two lines belong to the same paragraph when their marker and indent match.
+fn same_leader(a: &str, b: &str) -> bool {
+    a.trim_end() == b.trim_end()
+}
+
+/// Width of the bullet or number and the space after it that start a list item like `* `,
+/// `- ` or `1. `. Each item is a paragraph of its own.
+fn list_item(text: &str) -> Option<usize> {
+    let digits = text.chars().take_while(char::is_ascii_digit).count();
+    let marker = match (text[digits..].chars().next()?, digits) {
+        ('.' | ')', 1..) | ('*' | '-' | '+', 0) => digits + 1,
+        _ => return None,
+    };
+    text[marker..].starts_with(' ').then_some(marker + 1)
+}
+
+/// Lines kept as they are: blank ones, which separate paragraphs, and Markdown headings.
+fn kept(text: &str, filetype: &str) -> bool {
+    text.trim().is_empty() || (filetype == "markdown" && text.starts_with('#'))
+}
+
+/// Re-wraps `lines` so no line is longer than `width` unless it holds a single long word.
+/// Lines without text after their leader separate paragraphs and are kept as they are. The
+/// lines of a list item after the first are indented past its bullet.
+pub fn format_lines(lines: &[String], width: usize, filetype: &str) -> Vec<String> {
+    let width = if width == 0 { DEFAULT_WIDTH } else { width };
+    let mut formatted = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let (leader, text) = split_leader(&lines[i], filetype);
+        if kept(text, filetype) {
+            formatted.push(lines[i].clone());
+            i += 1;
+            continue;
+        }
+        let hanging = format!("{}{}", leader, " ".repeat(list_item(text).unwrap_or(0)));
+        let mut words = text.split_whitespace().collect::<Vec<&str>>();
+        i += 1;
+        while i < lines.len() {
+            let (next_leader, text) = split_leader(&lines[i], filetype);
+            if kept(text, filetype) || list_item(text).is_some() || !same_leader(&hanging, next_leader) {
+                break;
+            }
+            words.extend(text.split_whitespace());
+            i += 1;
+        }
+        let (mut line, mut prefix) = (leader.to_string(), leader.len());
+        for word in words {
+            let len = line.chars().count();
+            if len > prefix && len + 1 + word.chars().count() > width {
+                formatted.push(line);
+                line = hanging.clone();
+                prefix = hanging.len();
+            }
+            if line.len() > prefix {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        formatted.push(line);
+    }
+    formatted
+}
+
+/// Where insert mode auto wrap breaks `line` when it is longer than `width`, returns the
+/// char range of the whitespace to replace with a line break.
+pub fn wrap_point(line: &str, width: usize, filetype: &str) -> Option<std::ops::Range<usize>> {
+    let chars = line.chars().collect::<Vec<char>>();
+    if width == 0 || chars.len() <= width {
+        return None;
+    }
+    let leader = split_leader(line, filetype).0.chars().count();
+    let end = (leader..=width.min(chars.len() - 1)).rev().find(|i| chars[*i].is_whitespace())?;
+    let start = (leader..end).rev().take_while(|i| chars[*i].is_whitespace()).last().unwrap_or(end);
+    if start <= leader {
+        return None;
+    }
+    Some(start..end + 1)
+}
+
+#[test]
+fn test_format_comment_paragraphs() {
+    let lines = ["    /// Joins the lines", "    /// of a paragraph together.", "    ///", "    /// Next one."]
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<String>>();
+    let formatted = format_lines(&lines, 22, "rust");
+    assert_eq!(
+        formatted,
+        vec!["    /// Joins the", "    /// lines of a", "    /// paragraph", "    /// together.", "    ///", "    /// Next one."]
+    );
+}
+
+#[test]
+fn test_wrap_point() {
+    assert_eq!(wrap_point("// hello world", 10, "rust"), Some(8..9));
+    assert_eq!(wrap_point("short", 10, "rust"), None);
+    assert_eq!(wrap_point("    averyveryverylongword", 10, "rust"), None);
+}
+
+#[test]
+fn test_format_markdown() {
+    let lines = ["# A heading", "* first item", "that goes on", "* second", "", "# not a comment"]
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<String>>();
+    assert_eq!(
+        format_lines(&lines, 12, "markdown"),
+        vec!["# A heading", "* first item", "  that goes", "  on", "* second", "", "# not a comment"]
+    );
+    assert_eq!(split_leader("# comment", "python"), ("# ", "comment"));
+    assert_eq!(split_leader("# heading", "markdown"), ("", "# heading"));
+}
@@ -0,0 +1,162 @@
+use ropey::Rope;
+
+/// Bytes shown on each row of the hex view.
+pub const WIDTH: usize = 16;
+
+/// Cursor of the hex view of a buffer. While it is shown the rope holds one char per byte,
+/// read as `latin1`, so char offsets are byte offsets.
+#[derive(Debug, Default, Clone)]
+pub struct HexView {
+    /// Byte offset of the cursor.
+    pub offset: usize,
+    /// The next hex digit typed goes to the low half of the byte.
+    pub low: bool,
+    /// The cursor is in the ASCII column instead of the hex one.
+    pub ascii: bool,
+    /// First row on screen.
+    pub top: usize,
+}
+
+impl HexView {
+    /// Moves the cursor to `offset`, kept inside the `len` bytes of the buffer, one past the
+    /// last when `append`, and scrolls so its row is on the `height` rows shown.
+    pub fn goto(&mut self, offset: usize, len: usize, append: bool, height: usize) {
+        let last = if append { len } else { len.saturating_sub(1) };
+        self.offset = offset.min(last);
+        self.low = false;
+        self.top = top(self.top, self.offset / WIDTH, height);
+    }
+
+    /// `x`, deletes `count` bytes from the cursor on and keeps it on the byte after them.
+    pub fn delete(&mut self, rope: &mut Rope, count: usize, height: usize) {
+        let end = (self.offset + count).min(rope.len_chars());
+        rope.remove(self.offset.min(end)..end);
+        self.goto(self.offset, rope.len_chars(), false, height);
+    }
+
+    /// Screen column of the cursor on its row, see `format_row`.
+    pub fn column(&self) -> usize {
+        let byte = self.offset % WIDTH;
+        match self.ascii {
+            true => 61 + byte,
+            false => 10 + byte * 3 + (byte >= WIDTH / 2) as usize + self.low as usize,
+        }
+    }
+}
+
+/// First row shown so that `row` is on the `height` rows starting at `top` or nearest to it.
+pub fn top(top: usize, row: usize, height: usize) -> usize {
+    if row < top {
+        row
+    } else if row >= top + height.max(1) {
+        row + 1 - height.max(1)
+    } else {
+        top
+    }
+}
+
+/// A hex view for a file just read when it is binary: read as `latin1` and with a NUL byte
+/// near its start, like `git` and `grep` tell them apart.
+pub fn binary(rope: &Rope, encoding: &str) -> Option<HexView> {
+    (encoding == "latin1" && rope.chars().take(8000).any(|c| c == '\0')).then(HexView::default)
+}
+
+/// Row `row` of the view like `hexdump -C`: the offset, the bytes in hex in two groups of eight
+/// and the bytes as ASCII with a `.` for the others.
+pub fn format_row(rope: &Rope, row: usize) -> String {
+    let start = (row * WIDTH).min(rope.len_chars());
+    let bytes = rope.slice(start..(start + WIDTH).min(rope.len_chars())).chars().map(|c| c as u32 as u8);
+    let bytes = bytes.collect::<Vec<u8>>();
+    let mut line = format!("{:08x}  ", start);
+    for i in 0..WIDTH {
+        match bytes.get(i) {
+            Some(byte) => line.push_str(&format!("{:02x} ", byte)),
+            None => line.push_str("   "),
+        }
+        if i == WIDTH / 2 - 1 {
+            line.push(' ');
+        }
+    }
+    let ascii = bytes.iter().map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' });
+    format!("{} |{}|", line, ascii.collect::<String>())
+}
+
+/// Number of rows the view of `rope` has, an empty buffer still has one.
+pub fn rows(rope: &Rope) -> usize {
+    rope.len_chars().div_ceil(WIDTH).max(1)
+}
+
+/// The chars of the bytes a search in the hex view looks for: hex digit pairs like `7f454c46`
+/// or `de ad be ef` are bytes, anything else is searched for as text.
+pub fn parse_pattern(pattern: &str) -> String {
+    let digits = pattern.chars().filter(|c| !c.is_whitespace()).collect::<Vec<char>>();
+    if digits.is_empty() || digits.len() % 2 != 0 || !digits.iter().all(char::is_ascii_hexdigit) {
+        return pattern.to_string();
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(&pair.iter().collect::<String>(), 16).unwrap_or_default() as char)
+        .collect()
+}
+
+/// `byte` with its high or low half replaced by hex `digit`.
+pub fn set_nibble(byte: u8, digit: u8, low: bool) -> u8 {
+    match low {
+        true => (byte & 0xF0) | digit,
+        false => (byte & 0x0F) | (digit << 4),
+    }
+}
+
+#[test]
+fn test_format_row() {
+    let rope = Rope::from_str("\u{7f}ELF\u{2}\u{1}\u{1}\0\0\0\0\0\0\0\0\0hi");
+    assert_eq!(
+        format_row(&rope, 0),
+        "00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|"
+    );
+    assert_eq!(format_row(&rope, 1).trim_end(), "00000010  68 69                                             |hi|");
+    assert_eq!(rows(&rope), 2);
+    assert!(binary(&rope, "latin1").is_some());
+    assert!(binary(&rope, "utf-8").is_none());
+}
+
+#[test]
+fn test_edit_helpers() {
+    assert_eq!(parse_pattern("de ad"), "\u{de}\u{ad}");
+    assert_eq!(parse_pattern("ELF"), "ELF");
+    assert_eq!(set_nibble(0x12, 0xa, false), 0xa2);
+    assert_eq!(set_nibble(0x12, 0xa, true), 0x1a);
+    let mut view = HexView { offset: 17, ..HexView::default() };
+    assert_eq!(view.column(), 13);
+    view.ascii = true;
+    assert_eq!(view.column(), 62);
+    view.goto(100, 40, false, 2);
+    assert_eq!((view.offset, view.top), (39, 1));
+}
+
+#[test]
+fn test_delete() {
+    let mut rope = Rope::from_str("\u{7f}ELF\u{2}");
+    let mut view = HexView { offset: 1, ..HexView::default() };
+    view.delete(&mut rope, 2, 10);
+    assert_eq!((rope.to_string().as_str(), view.offset), ("\u{7f}F\u{2}", 1));
+    view.goto(2, rope.len_chars(), false, 10);
+    view.delete(&mut rope, 5, 10);
+    assert_eq!((rope.to_string().as_str(), view.offset), ("\u{7f}F", 1));
+}
+
+#[test]
+fn test_hex_reads_the_whole_file() {
+    use crate::{editor::Editor, keymapper::execute_command, largefile::Loader};
+    let path = std::env::temp_dir().join(format!("revim-hex-{}.txt", std::process::id()));
+    let text = "0123456789abcde\n".repeat(1 << 17);
+    std::fs::write(&path, &text).unwrap();
+    let path = path.to_str().unwrap_or_default();
+    let (first, _, loader) = Loader::open(path, 1, None).unwrap().unwrap();
+    let mut editor = Editor::new(Rope::from_str(&first), Some(path.to_string()));
+    editor.start_loading(editor.buffer, loader);
+    execute_command(&mut editor, "hex");
+    assert!(editor.hex.is_some() && editor.loading().is_none());
+    assert_eq!(editor.rope.len_chars(), text.len());
+    std::fs::remove_file(path).ok();
+}
@@ -0,0 +1,86 @@
+use crate::editor::Cursor;
+use ropey::Rope;
+
+/// Number of entries a history keeps.
+const HISTORY_SIZE: usize = 100;
+
+/// Lines entered on the command line or as search patterns, oldest first.
+#[derive(Debug, Default)]
+pub struct History {
+    pub entries: Vec<String>,
+    /// Entry shown by the last Up or Down and the text typed before browsing started.
+    browsing: Option<(usize, String)>,
+    recalled: String,
+}
+
+impl History {
+    /// Adds `entry` as the newest one, an older copy of it is dropped.
+    pub fn add(&mut self, entry: &str) {
+        self.browsing = None;
+        if entry.is_empty() {
+            return;
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.push(entry.to_string());
+        if self.entries.len() > HISTORY_SIZE {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Up on the command line, the next older entry starting with the text that was typed.
+    pub fn older(&mut self, typed: &str) -> Option<String> {
+        self.browse(typed, true)
+    }
+
+    /// Down on the command line, the next newer entry or the typed text past the newest one.
+    pub fn newer(&mut self, typed: &str) -> Option<String> {
+        self.browse(typed, false)
+    }
+
+    fn browse(&mut self, typed: &str, older: bool) -> Option<String> {
+        // Editing a recalled line starts browsing again with the edited text as prefix.
+        if self.browsing.is_none() || typed != self.recalled {
+            self.browsing = Some((self.entries.len(), typed.to_string()));
+        }
+        let (index, prefix) = self.browsing.clone()?;
+        let matches = |i: &usize| self.entries[*i].starts_with(&prefix);
+        let next = if older {
+            (0..index).rev().find(matches)
+        } else {
+            (index + 1..self.entries.len()).find(matches)
+        };
+        let (index, entry) = match next {
+            Some(i) => (i, self.entries[i].clone()),
+            None if older => return None,
+            None => (self.entries.len(), prefix.clone()),
+        };
+        self.browsing = Some((index, prefix));
+        self.recalled = entry.clone();
+        Some(entry)
+    }
+}
+
+/// The history opened with `q:` or `q/` in place of the file, `Enter` runs the line under the
+/// cursor and puts the file back.
+#[derive(Debug)]
+pub struct CmdWindow {
+    pub prompt: char,
+    pub rope: Rope,
+    pub file_path: Option<String>,
+    pub cursor: Cursor,
+    pub top: usize,
+}
+
+#[test]
+fn test_history_prefix_recall() {
+    let mut history = History::default();
+    for entry in ["set tw=80", "w", "set nf?", "w"] {
+        history.add(entry);
+    }
+    assert_eq!(history.entries, vec!["set tw=80", "set nf?", "w"]);
+    assert_eq!(history.older("set").as_deref(), Some("set nf?"));
+    assert_eq!(history.older("set nf?").as_deref(), Some("set tw=80"));
+    assert_eq!(history.older("set tw=80"), None);
+    assert_eq!(history.newer("set tw=80").as_deref(), Some("set nf?"));
+    assert_eq!(history.newer("set nf?").as_deref(), Some("set"));
+}
@@ -0,0 +1,138 @@
+/// Kinds of number `Ctrl-A` and `Ctrl-X` know how to change.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Kind {
+    Decimal,
+    Hex,
+    Octal,
+    Binary,
+    Alpha,
+}
+
+/// A number found on a line, `start..end` are char columns including any sign or prefix.
+#[derive(Debug)]
+struct Number {
+    start: usize,
+    end: usize,
+    kind: Kind,
+}
+
+/// Finds the first number that ends after `col`, so the one under the cursor or the next one
+/// after it. `nrformats` is the comma separated list from the option of the same name.
+fn find_number(line: &[char], col: usize, nrformats: &str) -> Option<Number> {
+    let has = |name: &str| nrformats.split(',').any(|f| f == name);
+    let mut i = 0;
+    while i < line.len() {
+        let c = line[i];
+        let next = line.get(i + 1).copied().unwrap_or(' ');
+        let after = line.get(i + 2).copied().unwrap_or(' ');
+        let run = |from: usize, f: fn(&char) -> bool| from + line[from..].iter().take_while(|c| f(c)).count();
+        let number = if c == '0' && (next == 'x' || next == 'X') && after.is_ascii_hexdigit() && has("hex") {
+            Some((i, run(i + 2, char::is_ascii_hexdigit), Kind::Hex))
+        } else if c == '0' && (next == 'b' || next == 'B') && (after == '0' || after == '1') && has("bin") {
+            Some((i, run(i + 2, |c| c == &'0' || c == &'1'), Kind::Binary))
+        } else if c.is_ascii_digit() {
+            let end = run(i, char::is_ascii_digit);
+            if c == '0' && end - i > 1 && line[i..end].iter().all(|c| c < &'8') && has("octal") {
+                Some((i, end, Kind::Octal))
+            } else if i > 0 && line[i - 1] == '-' && !has("unsigned") {
+                Some((i - 1, end, Kind::Decimal))
+            } else {
+                Some((i, end, Kind::Decimal))
+            }
+        } else if c.is_ascii_alphabetic() && has("alpha") {
+            Some((i, i + 1, Kind::Alpha))
+        } else {
+            None
+        };
+        match number {
+            Some((start, end, kind)) if end > col => return Some(Number { start, end, kind }),
+            Some((_, end, _)) => i = end,
+            None => i += 1,
+        }
+    }
+    None
+}
+
+/// Adds `delta` to the number under or after `col` in `line`. Returns the new line and the
+/// column of the last char of the changed number, or `None` when there is no number.
+pub fn increment(line: &str, col: usize, delta: i64, nrformats: &str) -> Option<(String, usize)> {
+    let chars = line.chars().collect::<Vec<char>>();
+    let number = find_number(&chars, col, nrformats)?;
+    let text = chars[number.start..number.end].iter().collect::<String>();
+    let new = match number.kind {
+        Kind::Decimal => {
+            let digits = text.trim_start_matches('-');
+            // Numbers too long for i128 stay at its limit, like Vim does for 64 bit numbers.
+            let value = digits.parse::<i128>().unwrap_or(i128::MAX);
+            let value = if text.starts_with('-') { -value } else { value }.saturating_add(delta as i128);
+            let value = if nrformats.split(',').any(|f| f == "unsigned") { value.max(0) } else { value };
+            let width = if digits.len() > 1 && digits.starts_with('0') { digits.len() } else { 0 };
+            let sign = if value < 0 { "-" } else { "" };
+            format!("{}{:0width$}", sign, value.unsigned_abs(), width = width)
+        }
+        Kind::Hex | Kind::Binary => {
+            let (prefix, digits) = text.split_at(2);
+            let radix = if number.kind == Kind::Hex { 16 } else { 2 };
+            let value = u64::from_str_radix(digits, radix).unwrap_or(u64::MAX).wrapping_add(delta as u64);
+            let width = digits.len();
+            let new = match radix {
+                16 if digits.chars().any(|c| c.is_ascii_uppercase()) => format!("{:0width$X}", value, width = width),
+                16 => format!("{:0width$x}", value, width = width),
+                _ => format!("{:0width$b}", value, width = width),
+            };
+            format!("{}{}", prefix, new)
+        }
+        Kind::Octal => {
+            let value = u64::from_str_radix(&text, 8).unwrap_or(u64::MAX).wrapping_add(delta as u64);
+            format!("0{:0width$o}", value, width = text.len() - 1)
+        }
+        Kind::Alpha => {
+            let c = chars[number.start];
+            let base = if c.is_ascii_lowercase() { b'a' } else { b'A' } as i64;
+            let value = (c as i64 - base + delta).clamp(0, 25) + base;
+            (value as u8 as char).to_string()
+        }
+    };
+    let mut result = chars[..number.start].iter().collect::<String>();
+    result.push_str(&new);
+    result.extend(&chars[number.end..]);
+    Some((result, number.start + new.chars().count() - 1))
+}
+
+/// Like `increment` for the number in columns `start..end` of `line`, the part of it a visual
+/// selection covers. Numbers and digits outside of it are left alone.
+pub fn increment_in(line: &str, start: usize, end: usize, delta: i64, nrformats: &str) -> Option<(String, usize)> {
+    let chars = line.chars().collect::<Vec<char>>();
+    let end = end.min(chars.len());
+    let start = start.min(end);
+    let (new, col) = increment(&chars[start..end].iter().collect::<String>(), 0, delta, nrformats)?;
+    let mut result = chars[..start].iter().collect::<String>();
+    result.push_str(&new);
+    result.extend(&chars[end..]);
+    Some((result, start + col))
+}
+
+#[test]
+fn test_increment_formats() {
+    let formats = "bin,hex";
+    assert_eq!(increment("x = 9;", 0, 1, formats), Some(("x = 10;".to_string(), 5)));
+    assert_eq!(increment("let a = -1;", 0, 3, formats), Some(("let a = 2;".to_string(), 8)));
+    assert_eq!(increment("0x0fF", 2, 1, formats), Some(("0x100".to_string(), 4)));
+    assert_eq!(increment("0b0111", 0, 1, formats), Some(("0b1000".to_string(), 5)));
+    assert_eq!(increment("007", 0, 1, formats), Some(("008".to_string(), 2)));
+    assert_eq!(increment("007", 0, 1, "octal"), Some(("010".to_string(), 2)));
+    assert_eq!(increment("a1 b2", 2, -5, formats), Some(("a1 b-3".to_string(), 5)));
+    assert_eq!(increment("no numbers", 0, 1, formats), None);
+    let huge = "9".repeat(40);
+    let max = i128::MAX.to_string();
+    assert_eq!(increment(&huge, 0, 1, formats), Some((max.clone(), max.len() - 1)));
+    assert_eq!(increment(&format!("-{}", huge), 0, -2, formats).map(|(line, _)| line), Some(i128::MIN.to_string()));
+}
+
+#[test]
+fn test_increment_in_selection() {
+    assert_eq!(increment_in("a1 b2 c3", 3, 5, 1, ""), Some(("a1 b3 c3".to_string(), 4)));
+    assert_eq!(increment_in("x = 1; y = 2", 0, 4, 1, ""), None);
+    assert_eq!(increment_in("x = 1; y = 2", 7, 20, 1, ""), Some(("x = 1; y = 3".to_string(), 11)));
+    assert_eq!(increment_in("1234", 0, 2, 1, ""), Some(("1334".to_string(), 1)));
+}
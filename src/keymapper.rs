@@ -1,14 +1,30 @@
-use crate::{Editor, editor::EditorError, Mode, usub, render::StringCount};
+use crate::{
+    abbrev, comment,
+    complete::{self, Completion},
+    digraph::{self, Literal},
+    edit,
+    editor::{EditorError, Pending},
+    filetype, format, hex,
+    history::CmdWindow,
+    increment,
+    options::Options,
+    pairs::{self, PairAction},
+    render::StringCount,
+    spell::{self, Speller},
+    swap::{self, Swap},
+    tab, usub, Editor, Mode, Rope,
+};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use std::collections::HashMap;
 
 type EditorEvent = Box<dyn Fn(&mut Editor)>;
-type KeyMap = HashMap<KeyEvent, EditorEvent>;
+type KeyMap = HashMap<Vec<KeyEvent>, EditorEvent>;
 
 pub struct Mapper {
     nmaps: KeyMap,
     imaps: KeyMap,
     cmaps: KeyMap,
+    vmaps: KeyMap,
 }
 
 impl Mapper {
@@ -17,6 +33,7 @@ impl Mapper {
             nmaps: KeyMap::new(),
             imaps: KeyMap::new(),
             cmaps: KeyMap::new(),
+            vmaps: KeyMap::new(),
         }
     }
 
@@ -26,6 +43,7 @@ impl Mapper {
             Normal => &self.nmaps,
             Insert => &self.imaps,
             Command => &self.cmaps,
+            Visual => &self.vmaps,
         }
     }
 
@@ -35,15 +53,103 @@ impl Mapper {
             Normal => &mut self.nmaps,
             Insert => &mut self.imaps,
             Command => &mut self.cmaps,
+            Visual => &mut self.vmaps,
         }
     }
 
-    pub fn get_mapping(&self, mode: &Mode, event: &KeyEvent) -> Option<&EditorEvent> {
-        Some(self.get_map(mode).get(event)?)
+    pub fn get_mapping(&self, mode: &Mode, keys: &[KeyEvent]) -> Option<&EditorEvent> {
+        self.get_map(mode).get(keys)
+    }
+
+    fn is_prefix(&self, mode: &Mode, keys: &[KeyEvent]) -> bool {
+        self.get_map(mode).keys().any(|k| k.len() > keys.len() && k.starts_with(keys))
+    }
+
+    /// Feeds one key press to the editor and records what it changed for undo. The history
+    /// window and switching buffers replace the text without changing it.
+    pub fn dispatch(&self, editor: &mut Editor, key: KeyEvent) {
+        let before = editor.cmdwin.is_none().then(|| (editor.buffer, editor.edits, editor.state()));
+        self.handle_key(editor, key);
+        if let Some((buffer, edits, state)) = before {
+            if editor.cmdwin.is_none() && editor.buffer == buffer {
+                let changed = editor.edits != edits;
+                if !editor.largefile {
+                    editor.undo.record(state, changed, editor.mode == Mode::Insert);
+                }
+                if changed {
+                    editor.modified = true;
+                    editor.changes += 1;
+                }
+                if editor.changes >= editor.options.updatecount.max(1) {
+                    editor.write_swap();
+                }
+            }
+        }
+    }
+
+    /// Digits in Normal and Visual mode build up `editor.count` and keys are collected in
+    /// `editor.keys` until they match a mapping.
+    fn handle_key(&self, editor: &mut Editor, key: KeyEvent) {
+        // Output over several lines covers the text, any key dismisses it.
+        if editor.output.contains('\n') {
+            editor.output.clear();
+        }
+        if editor.completion.is_some() && !complete::is_completion_key(&key) {
+            editor.completion = None;
+        }
+        if editor.wildmenu.is_some() && !complete::is_wildmenu_key(&key) {
+            editor.wildmenu = None;
+        }
+        if editor.pending.is_some() && pending_input(editor, key) {
+            return;
+        }
+        if editor.hex.is_some() && editor.keys.is_empty() && hex_key(editor, key) {
+            return;
+        }
+        if editor.keys.is_empty() && matches!(editor.mode, Mode::Normal | Mode::Visual) {
+            if let KeyCode::Char(c @ '0'..='9') = key.code {
+                if c != '0' || editor.count > 0 {
+                    editor.count = editor.count.saturating_mul(10).saturating_add(c as usize - '0' as usize);
+                    return;
+                }
+            }
+        }
+        editor.keys.push(key);
+        let keys = editor.keys.clone();
+        if let Some(handle) = self.get_mapping(&editor.mode, &keys) {
+            editor.keys.clear();
+            let one_command = editor.insert_after && editor.mode == Mode::Normal;
+            handle(editor);
+            editor.count = 0;
+            // `Ctrl-O` in Insert mode ran this command, go back to Insert mode.
+            if one_command && editor.mode == Mode::Normal {
+                editor.insert_after = false;
+                editor.mode = Mode::Insert;
+            }
+        } else if !self.is_prefix(&editor.mode, &keys) {
+            editor.keys.clear();
+            editor.count = 0;
+            // Chars without a mapping of their own, like non ASCII ones, are typed as text.
+            if let (KeyCode::Char(c), [_]) = (key.code, keys.as_slice()) {
+                if (key.modifiers - KeyModifiers::SHIFT).is_empty() {
+                    match editor.mode {
+                        Mode::Insert => insert_typed(editor, c),
+                        Mode::Command => command_typed(editor, c),
+                        _ => {}
+                    }
+                }
+            }
+        }
     }
 
     pub fn insert_mapping(mut self, mode: &Mode, key: KeyEvent, event: EditorEvent) -> Self {
-        self.get_map_mut(mode).insert(key, event);
+        self.get_map_mut(mode).insert(vec![key], event);
+        self
+    }
+
+    /// Maps a sequence of keys written like `gJ` or `g<C-a>`, see `parse_keys`.
+    pub fn insert_mapping_seq(mut self, mode: &Mode, keys: &str, event: EditorEvent) -> Self {
+        self.get_map_mut(mode).insert(parse_keys(keys), event);
         self
     }
 
@@ -52,16 +158,14 @@ impl Mapper {
             match mode {
                 Mode::Command => {
                     self.get_map_mut(mode).insert(
-                        KeyEvent::new(KeyCode::Char(c), modifier),
-                        Box::new(move |editor| editor.command.push(c)),
+                        vec![KeyEvent::new(KeyCode::Char(c), modifier)],
+                        Box::new(move |editor| command_typed(editor, c)),
                     );
                 }
                 Mode::Insert => {
                     self.get_map_mut(mode).insert(
-                        KeyEvent::new(KeyCode::Char(c), modifier),
-                        Box::new(move |editor| {
-                            insert_char_to_rope(editor, c);
-                        }),
+                        vec![KeyEvent::new(KeyCode::Char(c), modifier)],
+                        Box::new(move |editor| insert_typed(editor, c)),
                     );
                 }
                 _ => {}
@@ -71,8 +175,7 @@ impl Mapper {
     }
 
     pub fn key_adder(self, mode: &Mode) -> Self {
-        self
-            .insert_mapping_chain(
+        self.insert_mapping_chain(
                 mode,
                 ('a'..='z').collect::<String>().as_str(),
                 KeyModifiers::NONE,
@@ -100,83 +203,287 @@ impl Mapper {
         self.insert_mapping(
             &Normal,
             KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
-            Box::new(|editor| editor.is_running = false),
+            Box::new(|editor| quit(editor, false)),
         )
-        // Cursor Down
+        .movement(&Normal)
         .insert_mapping(
             &Normal,
-            KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE),
+            if cfg!(windows) {
+                KeyEvent::new(KeyCode::Char(':'), KeyModifiers::SHIFT)
+            } else {
+                KeyEvent::new(KeyCode::Char(':'), KeyModifiers::NONE)
+            },
+            Box::new(|editor| start_command_line(editor, ':')),
+        )
+        // Search
+        .insert_mapping_seq(&Normal, "/", Box::new(|editor| start_command_line(editor, '/')))
+        .insert_mapping_seq(&Normal, "?", Box::new(|editor| start_command_line(editor, '?')))
+        .insert_mapping_seq(
+            &Normal,
+            "n",
             Box::new(|editor| {
-                if editor.cursor.y != editor.screen.max_h.saturating_sub(1) as u16 {
-                    // This is for moving cursor
-                    editor.cursor.y = (editor.cursor.y + 1).min(
-                        (std::cmp::min(editor.screen.bottom(), editor.rope.len_lines().saturating_sub(2)))
-                            as u16);
-
-                    editor.cursor.gy = (editor.cursor.gy + 1).min(editor.rope.len_lines().saturating_sub(2) as u16);
-                } else {
-                    // This is for scrolling
-                    editor.screen.t = (editor.screen.t + 1).min(
-                        std::cmp::max(editor.screen.bottom(), editor.rope.len_lines().saturating_sub(2)));
-
-                    editor.cursor.gy = (editor.cursor.gy + 1).min(editor.rope.len_lines().saturating_sub(2) as u16);
+                let backward = editor.search_backward;
+                search(editor, "", backward);
+            }),
+        )
+        .insert_mapping_seq(
+            &Normal,
+            "N",
+            Box::new(|editor| {
+                let backward = editor.search_backward;
+                search(editor, "", !backward);
+                editor.search_backward = backward;
+            }),
+        )
+        // History Window
+        .insert_mapping_seq(&Normal, "q:", Box::new(|editor| open_cmdwin(editor, ':')))
+        .insert_mapping_seq(&Normal, "q/", Box::new(|editor| open_cmdwin(editor, '/')))
+        .insert_mapping_seq(&Normal, "q?", Box::new(|editor| open_cmdwin(editor, '?')))
+        .insert_mapping_seq(
+            &Normal,
+            "<CR>",
+            Box::new(|editor| {
+                if editor.cmdwin.is_none() {
+                    return cursor_down(editor);
                 }
-                editor.cursor.x = std::cmp::min(end_of_line_without_new_line(&editor), editor.cursor.max_x);
-                editor.cursor.gx = std::cmp::min(end_of_line_without_new_line(&editor), editor.cursor.max_x);
+                let line = edit::line_text(&editor.rope, editor.cursor.gy as usize);
+                let prompt = close_cmdwin(editor);
+                run_command_line(editor, prompt, line);
             }),
         )
-        // Cursor Up
-        .insert_mapping(
+        .insert_mapping_seq(
             &Normal,
-            KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE),
+            "<C-c>",
             Box::new(|editor| {
-                if editor.cursor.y != 0 {
-                    // This is for moving cursor
-                    editor.cursor.y = editor.cursor.y.saturating_sub(1);
-                    editor.cursor.gy = editor.cursor.gy.saturating_sub(1);
-                } else {
-                    // This is for scrolling
-                    editor.screen.t = editor.screen.t.saturating_sub(1);
-                    editor.cursor.gy = editor.cursor.gy.saturating_sub(1);
+                close_cmdwin(editor);
+            }),
+        )
+        // Undo
+        .insert_mapping_seq(&Normal, "u", Box::new(|editor| undo(editor, false)))
+        .insert_mapping_seq(&Normal, "<C-r>", Box::new(|editor| undo(editor, true)))
+        // Buffers
+        .insert_mapping_seq(
+            &Normal,
+            "<C-^>",
+            Box::new(|editor| {
+                let number = match editor.count {
+                    0 => editor.alternate.ok_or(EditorError::NoAlternateFile),
+                    count => editor.find_buffer(&count.to_string()),
+                };
+                match number {
+                    Ok(number) => switch_buffer(editor, number, false),
+                    Err(error) => editor.error = error,
                 }
-                editor.cursor.x = std::cmp::min(end_of_line_without_new_line(&editor), editor.cursor.max_x);
-                editor.cursor.gx = std::cmp::min(end_of_line_without_new_line(&editor), editor.cursor.max_x);
             }),
         )
-        // Cursor Left
-        .insert_mapping(
+        // Tab pages
+        .insert_mapping_seq(&Normal, "gt", Box::new(|editor| next_tab(editor, editor.count, true)))
+        .insert_mapping_seq(&Normal, "gT", Box::new(|editor| next_tab(editor, editor.count_or(1), false)))
+        // Windows
+        .insert_mapping_seq(&Normal, "<C-w>s", Box::new(|editor| editor.split_window(false)))
+        .insert_mapping_seq(&Normal, "<C-w>v", Box::new(|editor| editor.split_window(true)))
+        .insert_mapping_seq(&Normal, "<C-w>h", Box::new(|editor| window_beside(editor, 'h')))
+        .insert_mapping_seq(&Normal, "<C-w>j", Box::new(|editor| window_beside(editor, 'j')))
+        .insert_mapping_seq(&Normal, "<C-w>k", Box::new(|editor| window_beside(editor, 'k')))
+        .insert_mapping_seq(&Normal, "<C-w>l", Box::new(|editor| window_beside(editor, 'l')))
+        .insert_mapping_seq(&Normal, "<C-w>w", Box::new(next_window))
+        .insert_mapping_seq(&Normal, "<C-w><C-w>", Box::new(next_window))
+        .insert_mapping_seq(&Normal, "<C-w>+", Box::new(|editor| editor.resize_window(false, editor.count_or(1) as isize)))
+        .insert_mapping_seq(&Normal, "<C-w>-", Box::new(|editor| editor.resize_window(false, -(editor.count_or(1) as isize))))
+        .insert_mapping_seq(&Normal, "<C-w>>", Box::new(|editor| editor.resize_window(true, editor.count_or(1) as isize)))
+        .insert_mapping_seq(&Normal, "<C-w><lt>", Box::new(|editor| editor.resize_window(true, -(editor.count_or(1) as isize))))
+        .insert_mapping_seq(
             &Normal,
-            KeyEvent::new(KeyCode::Char('h'), KeyModifiers::NONE),
+            "<C-w>=",
             Box::new(|editor| {
-                editor.cursor.x = editor.cursor.x.saturating_sub(1);
-                editor.cursor.gx = editor.cursor.gx.saturating_sub(1);
-                editor.cursor.max_x = std::cmp::min(editor.cursor.gx, editor.cursor.max_x);
+                let area = editor.window_area();
+                editor.layout.equalize(area);
+                editor.fit_window();
             }),
         )
-        // Cursor Right
+        .insert_mapping_seq(&Normal, "<C-w>o", Box::new(|editor| editor.only_window()))
+        .insert_mapping_seq(&Normal, "<C-w>c", Box::new(|editor| execute_command(editor, "close")))
+        .insert_mapping_seq(&Normal, "<C-w>q", Box::new(|editor| execute_command(editor, "q")))
+        // Spelling
+        .insert_mapping_seq(&Normal, "]s", Box::new(|editor| next_misspelled(editor, true)))
+        .insert_mapping_seq(&Normal, "[s", Box::new(|editor| next_misspelled(editor, false)))
+        .insert_mapping_seq(&Normal, "z=", Box::new(suggest_spelling))
+        .insert_mapping_seq(&Normal, "zg", Box::new(|editor| add_spelling(editor, true)))
+        .insert_mapping_seq(&Normal, "zw", Box::new(|editor| add_spelling(editor, false)))
+        // Join Lines
         .insert_mapping(
             &Normal,
-            KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE),
+            KeyEvent::new(KeyCode::Char('J'), KeyModifiers::SHIFT),
             Box::new(|editor| {
-                editor.cursor.x = editor.cursor.x.saturating_add(1).min(end_of_line_without_new_line(&editor));
-                editor.cursor.gx = editor.cursor.gx.saturating_add(1).min(end_of_line_without_new_line(&editor));
-                editor.cursor.max_x = std::cmp::max(editor.cursor.x, editor.cursor.max_x);
+                let count = editor.count_or(2);
+                join(editor, editor.cursor.gy as usize, count, true);
+            }),
+        )
+        .insert_mapping_seq(
+            &Normal,
+            "gJ",
+            Box::new(|editor| {
+                let count = editor.count_or(2);
+                join(editor, editor.cursor.gy as usize, count, false);
+            }),
+        )
+        // Increment / Decrement Number
+        .insert_mapping_seq(
+            &Normal,
+            "<C-a>",
+            Box::new(|editor| {
+                let delta = editor.count_or(1) as i64;
+                let line = editor.cursor.gy as usize;
+                if let Some(col) = add_to_number(editor, line, editor.cursor.gx, delta) {
+                    editor.goto(line, col);
+                }
             }),
         )
+        .insert_mapping_seq(
+            &Normal,
+            "<C-x>",
+            Box::new(|editor| {
+                let delta = -(editor.count_or(1) as i64);
+                let line = editor.cursor.gy as usize;
+                if let Some(col) = add_to_number(editor, line, editor.cursor.gx, delta) {
+                    editor.goto(line, col);
+                }
+            }),
+        )
+    }
+
+    /// Maps an operator like `gq` that works on whole lines. It is followed by a motion,
+    /// doubled with its last key for the current line, or used on a visual selection.
+    fn operator(self, keys: &str, action: fn(&mut Editor, usize, usize)) -> Self {
+        use Mode::*;
+        type Motion = fn(&Editor) -> (usize, usize);
+        let motions: [(&str, Motion); 10] = [
+            (&keys[keys.len() - 1..], |e| (e.cursor.gy as usize, e.cursor.gy as usize + e.count_or(1) - 1)),
+            (keys, |e| (e.cursor.gy as usize, e.cursor.gy as usize + e.count_or(1) - 1)),
+            ("j", |e| (e.cursor.gy as usize, e.cursor.gy as usize + e.count_or(1))),
+            ("k", |e| ((e.cursor.gy as usize).saturating_sub(e.count_or(1)), e.cursor.gy as usize)),
+            ("G", |e| (e.cursor.gy as usize, if e.count == 0 { e.last_line() } else { e.count - 1 })),
+            ("gg", |e| (e.count.saturating_sub(1), e.cursor.gy as usize)),
+            ("ip", |e| edit::paragraph(&e.rope, e.cursor.gy as usize, false)),
+            ("ap", |e| edit::paragraph(&e.rope, e.cursor.gy as usize, true)),
+            ("}", |e| (e.cursor.gy as usize, next_blank_line(e, 1))),
+            ("{", |e| (next_blank_line(e, -1), e.cursor.gy as usize)),
+        ];
+        let mut mapper = self;
+        for (motion, lines) in motions.iter().copied() {
+            mapper = mapper.insert_mapping_seq(
+                &Normal,
+                &format!("{}{}", keys, motion),
+                Box::new(move |editor| {
+                    let (a, b) = lines(editor);
+                    let last = editor.last_line();
+                    action(editor, a.min(b).min(last), a.max(b).min(last));
+                }),
+            );
+        }
+        mapper.insert_mapping_seq(
+            &Visual,
+            keys,
+            Box::new(move |editor| {
+                let (start, end) = editor.visual_lines();
+                editor.mode = Normal;
+                action(editor, start, end);
+            }),
+        )
+    }
+
+    /// `Ctrl-K` digraphs and `Ctrl-V` literal chars, shared by Insert and Command mode.
+    fn special_chars(self, mode: &Mode) -> Self {
+        self.insert_mapping_seq(mode, "<C-k>", Box::new(|editor| editor.pending = Some(Pending::Digraph(None))))
+            .insert_mapping_seq(mode, "<C-v>", Box::new(|editor| editor.pending = Some(Pending::Literal(String::new()))))
+            .insert_mapping_seq(mode, "<C-q>", Box::new(|editor| editor.pending = Some(Pending::Literal(String::new()))))
+    }
+
+    /// `Ctrl-R {register}` puts the contents of a register where the cursor is.
+    fn register_inserts(self, mode: &Mode) -> Self {
+        let mut mapper = self;
+        for name in ('a'..='z').chain('0'..='9').chain("\"%:/-.".chars()) {
+            let insert: EditorEvent = match mode {
+                Mode::Command => Box::new(move |editor| {
+                    let text = editor.register(name).unwrap_or_default();
+                    command_insert(editor, &text.replace('\n', " "));
+                }),
+                _ => Box::new(move |editor| {
+                    let text = editor.register(name).unwrap_or_default();
+                    insert_str_to_rope(editor, &text);
+                }),
+            };
+            mapper = mapper.insert_mapping_seq(mode, &format!("<C-r>{}", name), insert);
+        }
+        mapper
+    }
+
+    /// hjkl cursor movement shared by Normal and Visual mode.
+    fn movement(self, mode: &Mode) -> Self {
+        self.insert_mapping(mode, KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE), Box::new(cursor_down))
+            .insert_mapping(mode, KeyEvent::new(KeyCode::Char('k'), KeyModifiers::NONE), Box::new(cursor_up))
+            .insert_mapping(mode, KeyEvent::new(KeyCode::Char('h'), KeyModifiers::NONE), Box::new(cursor_left))
+            .insert_mapping(mode, KeyEvent::new(KeyCode::Char('l'), KeyModifiers::NONE), Box::new(cursor_right))
+    }
+
+    fn build_visual(self) -> Self {
+        use Mode::*;
+        /* Visual Mode */
+        self.insert_mapping(
+            &Normal,
+            KeyEvent::new(KeyCode::Char('v'), KeyModifiers::NONE),
+            Box::new(|editor| start_visual(editor, false)),
+        )
         .insert_mapping(
             &Normal,
-            if cfg!(windows) {
-                KeyEvent::new(KeyCode::Char(':'), KeyModifiers::SHIFT)
-            } else {
-                KeyEvent::new(KeyCode::Char(':'), KeyModifiers::NONE)
-            },
+            KeyEvent::new(KeyCode::Char('V'), KeyModifiers::SHIFT),
+            Box::new(|editor| start_visual(editor, true)),
+        )
+        .insert_mapping(
+            &Visual,
+            KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+            Box::new(|editor| editor.mode = Normal),
+        )
+        .movement(&Visual)
+        .insert_mapping(
+            &Visual,
+            KeyEvent::new(KeyCode::Char('J'), KeyModifiers::SHIFT),
+            Box::new(|editor| {
+                let (start, end) = editor.visual_lines();
+                join(editor, start, end - start + 1, true);
+                editor.mode = Normal;
+            }),
+        )
+        .insert_mapping_seq(
+            &Visual,
+            "gJ",
             Box::new(|editor| {
-                editor.mode = Command;
-                editor.command.clear();
-                editor.error = EditorError::NONE;
-                editor.output = String::new();
+                let (start, end) = editor.visual_lines();
+                join(editor, start, end - start + 1, false);
+                editor.mode = Normal;
             }),
         )
+        .operator("gq", |editor, start, end| {
+            format_lines(editor, start, end);
+            let line = editor.cursor.gy as usize;
+            let col = edit::line_text(&editor.rope, line).chars().take_while(|c| c.is_whitespace()).count();
+            editor.goto(line, col);
+        })
+        .operator("gw", |editor, start, end| {
+            let (col, line) = (editor.cursor.gx, editor.cursor.gy as usize);
+            format_lines(editor, start, end);
+            editor.goto(line, col);
+        })
+        .operator("gc", |editor, start, end| {
+            let lines = (start..=end).map(|line| edit::line_text(&editor.rope, line)).collect::<Vec<String>>();
+            let markers = filetype::comment_markers(&editor.options.filetype);
+            edit::replace_lines(editor.rope_mut(), start, end, &comment::toggle_comment(&lines, markers));
+            editor.goto(start, editor.cursor.gx);
+        })
+        .insert_mapping_seq(&Visual, "<C-a>", Box::new(|editor| add_to_selection(editor, 1, false)))
+        .insert_mapping_seq(&Visual, "<C-x>", Box::new(|editor| add_to_selection(editor, -1, false)))
+        .insert_mapping_seq(&Visual, "g<C-a>", Box::new(|editor| add_to_selection(editor, 1, true)))
+        .insert_mapping_seq(&Visual, "g<C-x>", Box::new(|editor| add_to_selection(editor, -1, true)))
     }
 
     fn build_insert(self) -> Self {
@@ -185,33 +492,43 @@ impl Mapper {
         self.insert_mapping(
             &Normal,
             KeyEvent::new(KeyCode::Char('i'), KeyModifiers::NONE),
-            Box::new(|editor| editor.mode = Mode::Insert),
+            Box::new(|editor| {
+                editor.mode = Mode::Insert;
+                editor.insert_start = (editor.cursor.gx, editor.cursor.gy as usize);
+            }),
         )
         .insert_mapping(
             &Insert,
             KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE),
             Box::new(|editor| {
+                expand_abbreviation(editor);
                 insert_str_to_rope(editor, "    ");
             }),
         )
         .insert_mapping(
             &Insert,
             KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
-            Box::new(|editor| editor.mode = Normal),
+            Box::new(|editor| {
+                expand_abbreviation(editor);
+                editor.mode = Normal;
+            }),
         )
         .insert_mapping(
             &Insert,
             KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
             Box::new(|editor| {
-                insert_char_to_rope(editor, '\n');
-                editor.cursor.x = 0;
-                editor.cursor.y += 1;
+                expand_abbreviation(editor);
+                if open_block(editor) {
+                    return;
+                }
+                insert_str_to_rope(editor, edit::fileformat_ending(&editor.options.fileformat));
             }),
         )
         .insert_mapping(
             &Insert,
             KeyEvent::new(KeyCode::Char(' '), KeyModifiers::NONE),
             Box::new(|editor| {
+                expand_abbreviation(editor);
                 insert_char_to_rope(editor, ' ');
             }),
         )
@@ -219,21 +536,129 @@ impl Mapper {
             &Insert,
             KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE),
             Box::new(|editor| {
-                if editor.cursor.x == 0 && editor.cursor.y == 0 {
-                    return;
+                if !delete_pair(editor) {
+                    backspace(editor);
                 }
-
-                let line_index = editor.rope.line_to_char(editor.cursor.y as usize);
-                let index = (line_index as u16 + editor.cursor.x - 1) as usize;
-                editor.rope.remove(index..index + 1);
-
-                let new_line = editor.rope.char_to_line(index);
-                if new_line != editor.cursor.y as usize {
-                    editor.cursor.y -= usub(editor.cursor.y, 1);
+            }),
+        )
+        .insert_mapping_seq(
+            &Insert,
+            "<Del>",
+            Box::new(|editor| {
+                let index = editor.rope.line_to_char(editor.cursor.gy as usize) + editor.cursor.gx;
+                let line = editor.cursor.gy as usize;
+                if editor.cursor.gx < edit::line_len(&editor.rope, line) {
+                    editor.rope_mut().remove(index..index + 1);
+                } else if line < editor.last_line() {
+                    let next = editor.rope.line_to_char(line + 1);
+                    editor.rope_mut().remove(index..next);
+                }
+            }),
+        )
+        .insert_mapping_seq(
+            &Insert,
+            "<C-w>",
+            Box::new(|editor| {
+                if editor.cursor.gx == 0 {
+                    return backspace(editor);
+                }
+                let line = editor.cursor.gy as usize;
+                let before = edit::line_text(&editor.rope, line).chars().take(editor.cursor.gx).collect::<String>();
+                let col = before[..edit::word_start_before(&before)].chars().count();
+                delete_back_to(editor, col);
+            }),
+        )
+        .insert_mapping_seq(
+            &Insert,
+            "<C-u>",
+            Box::new(|editor| {
+                let (start_col, start_line) = editor.insert_start;
+                let col = editor.cursor.gx;
+                if start_line == editor.cursor.gy as usize && start_col < col {
+                    delete_back_to(editor, start_col);
+                } else if col > 0 {
+                    delete_back_to(editor, 0);
+                } else {
+                    backspace(editor);
+                }
+            }),
+        )
+        .insert_mapping_seq(
+            &Insert,
+            "<C-o>",
+            Box::new(|editor| {
+                editor.mode = Normal;
+                editor.insert_after = true;
+            }),
+        )
+        .register_inserts(&Insert)
+        .special_chars(&Insert)
+        // Insert Cursor Movement
+        .insert_mapping_seq(
+            &Insert,
+            "<Left>",
+            Box::new(|editor| {
+                let (col, line) = (editor.cursor.gx, editor.cursor.gy as usize);
+                match (col, line) {
+                    (0, 0) => {}
+                    (0, line) => editor.goto(line - 1, edit::line_len(&editor.rope, line - 1)),
+                    (col, line) => editor.goto(line, col - 1),
+                }
+            }),
+        )
+        .insert_mapping_seq(
+            &Insert,
+            "<Right>",
+            Box::new(|editor| {
+                let (col, line) = (editor.cursor.gx, editor.cursor.gy as usize);
+                if col < edit::line_len(&editor.rope, line) {
+                    editor.goto(line, col + 1);
+                } else if line < editor.last_line() {
+                    editor.goto(line + 1, 0);
                 }
-                editor.cursor.x = (index - editor.rope.line_to_char(new_line)) as u16;
             }),
         )
+        .insert_mapping_seq(
+            &Insert,
+            "<Up>",
+            Box::new(|editor| editor.goto(usub(editor.cursor.gy as usize, 1), editor.cursor.gx)),
+        )
+        .insert_mapping_seq(
+            &Insert,
+            "<Down>",
+            Box::new(|editor| editor.goto(editor.cursor.gy as usize + 1, editor.cursor.gx)),
+        )
+        .insert_mapping_seq(&Insert, "<Home>", Box::new(|editor| editor.goto(editor.cursor.gy as usize, 0)))
+        .insert_mapping_seq(
+            &Insert,
+            "<End>",
+            Box::new(|editor| {
+                let line = editor.cursor.gy as usize;
+                editor.goto(line, edit::line_len(&editor.rope, line));
+            }),
+        )
+        // Completion
+        .insert_mapping_seq(&Insert, "<C-n>", Box::new(|editor| complete_keyword(editor, 1)))
+        .insert_mapping_seq(&Insert, "<C-p>", Box::new(|editor| complete_keyword(editor, -1)))
+        .insert_mapping_seq(
+            &Insert,
+            "<C-x><C-f>",
+            Box::new(|editor| {
+                editor.completion = None;
+                complete_path(editor);
+                select_completion(editor, 1);
+            }),
+        )
+        .insert_mapping_seq(
+            &Insert,
+            "<C-e>",
+            Box::new(|editor| {
+                if let Some(completion) = editor.completion.take() {
+                    replace_completed(editor, completion.text(), &completion.original);
+                }
+            }),
+        )
+        .insert_mapping_seq(&Insert, "<C-y>", Box::new(|editor| editor.completion = None))
         .key_adder(&Insert)
     }
 
@@ -249,64 +674,1545 @@ impl Mapper {
             &Command,
             KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
             Box::new(|editor| {
-                editor.mode = Normal;
+                leave_command_line(editor);
                 editor.command = vec![" "; editor.screen.max_w].into_iter().collect();
+                editor.command_cursor = 0;
             }),
         )
         .insert_mapping(
             &Command,
             KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE),
             Box::new(|editor| {
-                let _ = editor.command.pop();
+                let start = command_char_before(editor);
+                editor.command.replace_range(start..editor.command_cursor, "");
+                editor.command_cursor = start;
             })
         )
+        // Line Editing
+        .insert_mapping_seq(
+            &Command,
+            "<Del>",
+            Box::new(|editor| {
+                let end = command_char_after(editor);
+                editor.command.replace_range(editor.command_cursor..end, "");
+            }),
+        )
+        .insert_mapping_seq(&Command, "<Left>", Box::new(|editor| editor.command_cursor = command_char_before(editor)))
+        .insert_mapping_seq(&Command, "<Right>", Box::new(|editor| editor.command_cursor = command_char_after(editor)))
+        .insert_mapping_seq(&Command, "<Home>", Box::new(|editor| editor.command_cursor = 0))
+        .insert_mapping_seq(&Command, "<C-b>", Box::new(|editor| editor.command_cursor = 0))
+        .insert_mapping_seq(&Command, "<End>", Box::new(|editor| editor.command_cursor = editor.command.len()))
+        .insert_mapping_seq(&Command, "<C-e>", Box::new(|editor| editor.command_cursor = editor.command.len()))
+        .insert_mapping_seq(
+            &Command,
+            "<C-w>",
+            Box::new(|editor| {
+                let before = &editor.command[..editor.command_cursor];
+                let start = edit::word_start_before(before);
+                editor.command.replace_range(start..editor.command_cursor, "");
+                editor.command_cursor = start;
+            }),
+        )
+        .insert_mapping_seq(
+            &Command,
+            "<C-u>",
+            Box::new(|editor| {
+                editor.command.replace_range(..editor.command_cursor, "");
+                editor.command_cursor = 0;
+            }),
+        )
+        .insert_mapping_seq(
+            &Command,
+            "<C-r><C-w>",
+            Box::new(|editor| {
+                let word = word_under_cursor(editor);
+                command_insert(editor, &word);
+            }),
+        )
+        .register_inserts(&Command)
+        .special_chars(&Command)
         .key_adder(&Command)
+        .insert_mapping_chain(&Command, " ", KeyModifiers::NONE)
+        .insert_mapping_seq(&Command, "<Tab>", Box::new(|editor| complete_command(editor, 1)))
+        .insert_mapping_seq(&Command, "<S-Tab>", Box::new(|editor| complete_command(editor, -1)))
         .insert_mapping(
             &Command,
             KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
             Box::new(|editor: &mut Editor| {
-                match editor.command.as_str() {
-                    "q" => editor.is_running = false,
-                    "w" => {
-                            editor.rope.write_to(
-                            std::io::BufWriter::new(
-                                std::fs::File::create(editor.file_path.clone().unwrap()).expect("File Did Not save!"))).expect("Rope Did not save");
-                            editor.error = EditorError::NONE;
-                        }
-                    "lenline" => editor.output = end_of_line_without_new_line(&editor).to_string(),
-                    "height" => editor.output = editor.screen.max_h.to_string(),
-                    "line" => editor.output = editor.rope.line(editor.cursor.gy as usize).chars().collect::<String>().trim_end().to_string(),
-                    c => editor.error = EditorError::InvalidCommand(c.to_string()),
+                if editor.prompt == ':' {
+                    expand_abbreviation(editor);
                 }
-                editor.mode = Mode::Normal;
-                editor.command = String::new();// vec![" "; editor.screen.max_w].into_iter().collect();
+                let command = std::mem::take(&mut editor.command);
+                editor.command_cursor = 0;
+                leave_command_line(editor);
+                run_command_line(editor, editor.prompt, command);
             }),
         )
+        .insert_mapping_seq(
+            &Command,
+            "<Up>",
+            Box::new(|editor| {
+                let typed = editor.command.clone();
+                if let Some(entry) = editor.history_mut(editor.prompt).older(&typed) {
+                    command_set(editor, entry);
+                }
+            }),
+        )
+        .insert_mapping_seq(
+            &Command,
+            "<Down>",
+            Box::new(|editor| {
+                let typed = editor.command.clone();
+                if let Some(entry) = editor.history_mut(editor.prompt).newer(&typed) {
+                    command_set(editor, entry);
+                }
+            }),
+        )
+    }
+}
+
+/// Puts `text` on the command line at its cursor.
+fn command_insert(editor: &mut Editor, text: &str) {
+    editor.command.insert_str(editor.command_cursor, text);
+    editor.command_cursor += text.len();
+}
+
+/// Replaces the whole command line and puts its cursor at the end.
+fn command_set(editor: &mut Editor, text: String) {
+    editor.command_cursor = text.len();
+    editor.command = text;
+}
+
+/// Byte offset of the char before the command line cursor.
+fn command_char_before(editor: &Editor) -> usize {
+    let before = &editor.command[..editor.command_cursor];
+    before.chars().next_back().map_or(0, |c| editor.command_cursor - c.len_utf8())
+}
+
+/// Byte offset of the char after the command line cursor.
+fn command_char_after(editor: &Editor) -> usize {
+    let after = &editor.command[editor.command_cursor..];
+    after.chars().next().map_or(editor.command.len(), |c| editor.command_cursor + c.len_utf8())
+}
+
+/// Keyword under or after the cursor in the text, `Ctrl-R Ctrl-W` on the command line.
+fn word_under_cursor(editor: &Editor) -> String {
+    let line = edit::line_text(&editor.rope, editor.cursor.gy as usize).chars().collect::<Vec<char>>();
+    let col = editor.cursor.gx;
+    let start = match line.get(col).copied().filter(|c| edit::is_keyword(*c)) {
+        Some(_) => col - line[..col].iter().rev().take_while(|c| edit::is_keyword(**c)).count(),
+        None => match line.iter().skip(col).position(|c| edit::is_keyword(*c)) {
+            Some(offset) => col + offset,
+            None => return String::new(),
+        },
+    };
+    line[start..].iter().take_while(|c| edit::is_keyword(**c)).collect()
+}
+
+/// Opens the command line for commands with `:` or searches with `/` and `?`.
+fn start_command_line(editor: &mut Editor, prompt: char) {
+    editor.mode = Mode::Command;
+    editor.prompt = prompt;
+    command_set(editor, String::new());
+    editor.error = EditorError::NONE;
+    editor.output = String::new();
+}
+
+/// Goes back to Normal mode, or to Insert mode when the command line was opened with `Ctrl-O`.
+fn leave_command_line(editor: &mut Editor) {
+    if editor.insert_after {
+        editor.insert_after = false;
+        editor.mode = Mode::Insert;
+    } else {
+        editor.mode = Mode::Normal;
+    }
+}
+
+/// Runs a line entered after `prompt` and keeps it in its history.
+fn run_command_line(editor: &mut Editor, prompt: char, line: String) {
+    editor.history_mut(prompt).add(&line);
+    if prompt == ':' {
+        if !line.is_empty() {
+            editor.registers.insert(':', line.clone());
+        }
+        execute_command(editor, &line);
+    } else {
+        search(editor, &line, prompt == '?');
+    }
+}
+
+/// Moves the cursor to the next match of `pattern`, the last pattern when it is empty.
+fn search(editor: &mut Editor, pattern: &str, backward: bool) {
+    let pattern = match pattern {
+        "" => editor.registers.get(&'/').cloned().unwrap_or_default(),
+        pattern => pattern.to_string(),
+    };
+    editor.registers.insert('/', pattern.clone());
+    editor.search_backward = backward;
+    if let Some(mut view) = editor.hex.take() {
+        match edit::find(&editor.rope, &hex::parse_pattern(&pattern), view.offset, !backward) {
+            Some(offset) => view.goto(offset, editor.rope.len_chars(), false, editor.screen.max_h),
+            None => editor.error = EditorError::PatternNotFound(pattern),
+        }
+        editor.hex = Some(view);
+        return;
+    }
+    let from = editor.rope.line_to_char(editor.cursor.gy as usize) + editor.cursor.gx;
+    match edit::find(&editor.rope, &pattern, from, !backward) {
+        Some(index) => {
+            let line = editor.rope.char_to_line(index);
+            editor.goto(line, index - editor.rope.line_to_char(line));
+        }
+        None => editor.error = EditorError::PatternNotFound(pattern),
+    }
+}
+
+/// `q:` and `q/`, shows the history in place of the file so a line can be edited and run.
+fn open_cmdwin(editor: &mut Editor, prompt: char) {
+    if editor.cmdwin.is_some() {
+        return;
+    }
+    let mut text = editor.history_mut(prompt).entries.join("\n");
+    text.push('\n');
+    let cmdwin = CmdWindow {
+        prompt,
+        rope: std::mem::replace(&mut editor.rope, Rope::from_str(&text)),
+        file_path: editor.file_path.take(),
+        cursor: std::mem::take(&mut editor.cursor),
+        top: editor.screen.t,
+    };
+    editor.cmdwin = Some(cmdwin);
+    editor.screen.t = 0;
+    editor.goto(editor.last_line(), 0);
+}
+
+/// Puts the file back in place of the history window and returns what the window was for.
+fn close_cmdwin(editor: &mut Editor) -> char {
+    match editor.cmdwin.take() {
+        Some(cmdwin) => {
+            editor.rope = cmdwin.rope;
+            editor.file_path = cmdwin.file_path;
+            editor.cursor = cmdwin.cursor;
+            editor.screen.t = cmdwin.top;
+            cmdwin.prompt
+        }
+        None => ':',
+    }
+}
+
+/// `u` and `Ctrl-R`, goes back or forward through the changes `count` times.
+fn undo(editor: &mut Editor, redo: bool) {
+    for _ in 0..editor.count_or(1) {
+        let current = editor.state();
+        let state = if redo { editor.undo.redo(current) } else { editor.undo.undo(current) };
+        match state {
+            Some(state) => editor.restore(state),
+            None => break,
+        }
+    }
+}
+
+/// Edits buffer `number` unless the buffer being edited has unsaved changes and `force`
+/// is not given.
+fn switch_buffer(editor: &mut Editor, number: usize, force: bool) {
+    match editor.modified_name() {
+        Some(name) if !force && number != editor.buffer => {
+            editor.error = EditorError::NoWriteSinceLastChange(name)
+        }
+        _ => editor.switch_buffer(number),
+    }
+}
+
+/// `:edit`, edits `path` in its own buffer or reads the file of this buffer again. A leading
+/// `++enc=name` reads the file in that encoding.
+fn edit_file(editor: &mut Editor, args: &str, force: bool) {
+    let (encoding, path) = match args.strip_prefix("++enc=").or_else(|| args.strip_prefix("++encoding=")) {
+        Some(rest) => {
+            let (name, path) = rest.split_at(rest.find(' ').unwrap_or(rest.len()));
+            (Some(name), path.trim())
+        }
+        None => (None, args),
+    };
+    if path.is_empty() {
+        if let Some(name) = editor.modified_name().filter(|_| !force) {
+            editor.error = EditorError::NoWriteSinceLastChange(name);
+            return;
+        }
+        let file = match &editor.file_path {
+            Some(file) => crate::commandline::open_path(file, encoding, editor.options.largefile),
+            None => Ok((Rope::new(), "utf-8", None)),
+        };
+        let (rope, encoding, loader) = match file {
+            Ok(file) => file,
+            Err(error) => return editor.error = error,
+        };
+        let number = editor.buffer;
+        editor.loaders.retain(|loader| loader.buffer != number);
+        editor.largefile = false;
+        if let Some(loader) = loader {
+            editor.start_loading(number, loader);
+        }
+        let (col, line) = (editor.cursor.gx, editor.cursor.gy as usize);
+        editor.options.fileformat = edit::detect_fileformat(&rope).to_string();
+        editor.options.fileencoding = encoding.to_string();
+        editor.hex = hex::binary(&rope, encoding);
+        editor.rope = rope;
+        editor.modified = false;
+        editor.changes = 0;
+        editor.undo.clear();
+        if let Some(path) = &editor.file_path {
+            swap::remove(path);
+        }
+        return editor.goto(line, col);
+    }
+    // A file already in the buffer list is read again when another encoding is asked for.
+    let listed = editor.buffers.iter().find(|b| b.file_path.as_deref() == Some(path)).map(|b| b.number);
+    let listed = listed.or_else(|| (editor.file_path.as_deref() == Some(path)).then_some(editor.buffer));
+    if let (Some(number), Some(name)) = (listed, encoding) {
+        switch_buffer(editor, number, force);
+        if editor.buffer == number {
+            edit_file(editor, &format!("++enc={}", name), force);
+        }
+        return;
+    }
+    let (number, swap) = match editor.buffers.iter().find(|b| b.file_path.as_deref() == Some(path)) {
+        Some(buffer) => (buffer.number, None),
+        None if editor.file_path.as_deref() == Some(path) => (editor.buffer, None),
+        None => {
+            let (rope, encoding, loader) = match crate::commandline::open_path(path, encoding, editor.options.largefile) {
+                Ok(file) => file,
+                Err(error) => return editor.error = error,
+            };
+            let swap = swap::find(path);
+            let hex = hex::binary(&rope, encoding);
+            let number = editor.add_buffer(rope, Some(path.to_string()));
+            if let Some(buffer) = editor.buffers.last_mut() {
+                buffer.fileencoding = encoding.to_string();
+                buffer.hex = hex;
+            }
+            if let Some(loader) = loader {
+                editor.start_loading(number, loader);
+            }
+            (number, swap)
+        }
+    };
+    switch_buffer(editor, number, force);
+    if editor.buffer == number {
+        editor.load_undo();
+    }
+    if let Some(swap) = swap.filter(|_| editor.buffer == number) {
+        offer_recovery(editor, swap);
+    }
+}
+
+/// Asks what to do with the swap file found for the file just opened, see `Swap::message`.
+pub fn offer_recovery(editor: &mut Editor, swap: Swap) {
+    let file = editor.file_path.clone().unwrap_or_default();
+    editor.output = swap.message(&file);
+    editor.pending = Some(Pending::Swap(swap));
+}
+
+/// Answers the question of `offer_recovery`. Quitting, also with `Esc`, leaves the file alone:
+/// its buffer is closed, or the editor when it is the only one.
+fn answer_recovery(editor: &mut Editor, swap: Swap, answer: char) {
+    match answer.to_ascii_lowercase() {
+        'r' => match swap.recover() {
+            Ok(rope) => {
+                *editor.rope_mut() = rope;
+                editor.goto(editor.cursor.gy as usize, editor.cursor.gx);
+            }
+            Err(error) => editor.error = EditorError::Io(format!("{}: {}", swap.path.display(), error)),
+        },
+        'o' => editor.readonly = true,
+        'd' => {
+            std::fs::remove_file(&swap.path).ok();
+        }
+        'q' | 'a' if editor.buffers.len() > 1 => editor.delete_buffer(editor.buffer),
+        'q' | 'a' => editor.is_running = false,
+        _ => {}
     }
 }
 
+/// `:bnext` and `:bprevious`, moves `count` buffers through the list and wraps around.
+fn next_buffer(editor: &mut Editor, count: &str, forward: bool, force: bool) {
+    let count = count.trim().parse::<usize>().unwrap_or(1);
+    let len = editor.buffers.len();
+    let index = editor.buffers.iter().position(|b| b.number == editor.buffer).unwrap_or(0);
+    let index = if forward { (index + count) % len } else { (index + len - count % len) % len };
+    switch_buffer(editor, editor.buffers[index].number, force);
+}
+
+/// `:bdelete`, removes the named buffer or the one being edited from the list.
+fn delete_buffer(editor: &mut Editor, arg: &str, force: bool) {
+    let number = match arg.trim() {
+        "" => editor.buffer,
+        arg => match editor.find_buffer(arg) {
+            Ok(number) => number,
+            Err(error) => return editor.error = error,
+        },
+    };
+    let buffer = editor.buffers.iter().find(|b| b.number == number && b.modified);
+    let name = match number == editor.buffer {
+        true => editor.modified_name(),
+        false => buffer.map(|b| b.name().to_string()),
+    };
+    match name {
+        Some(name) if !force => editor.error = EditorError::NoWriteSinceLastChange(name),
+        _ => editor.delete_buffer(number),
+    }
+}
+
+/// `Ctrl-W h`, `j`, `k` and `l`, enters the window in that direction.
+fn window_beside(editor: &mut Editor, direction: char) {
+    let rects = editor.window_rects();
+    let x = crate::render::display_column(&editor.rope, editor.cursor.gy as usize, editor.cursor.gx);
+    let cursor = (editor.screen.l + x, editor.screen.b + editor.cursor.y as usize);
+    let mut id = editor.window;
+    for _ in 0..editor.count_or(1) {
+        match crate::window::neighbour(&rects, id, direction, cursor) {
+            Some(next) => id = next,
+            None => break,
+        }
+    }
+    editor.enter_window(id);
+}
+
+/// `Ctrl-W w`, enters the next window and wraps around, with a count window `count`.
+fn next_window(editor: &mut Editor) {
+    let ids = editor.window_rects().into_iter().map(|(id, _)| id).collect::<Vec<usize>>();
+    let index = match editor.count {
+        0 => (ids.iter().position(|id| *id == editor.window).unwrap_or(0) + 1) % ids.len(),
+        count => count.min(ids.len()) - 1,
+    };
+    editor.enter_window(ids[index]);
+}
+
+/// `gt` and `gT`, shows the next or previous tab page and wraps around. Going forward a
+/// count is the number of the tab page to show, going back the number of tab pages to skip.
+fn next_tab(editor: &mut Editor, count: usize, forward: bool) {
+    let len = editor.tabs.len();
+    let index = match (forward, count) {
+        (true, 0) => (editor.tab + 1) % len,
+        (true, count) => count.min(len) - 1,
+        (false, count) => (editor.tab + len - count % len) % len,
+    };
+    editor.switch_tab(index);
+}
+
+/// Shows the error of a command that returns one.
+fn report(result: Result<(), EditorError>, editor: &mut Editor) {
+    if let Err(error) = result {
+        editor.error = error;
+    }
+}
+
+/// `:q` and `:qa`, leaves the editor unless a buffer has unsaved changes and `force` is not
+/// given.
+fn quit(editor: &mut Editor, force: bool) {
+    let others = editor.buffers.iter().filter(|b| b.number != editor.buffer && b.modified);
+    let modified = editor.modified_name().or_else(|| others.map(|b| b.name().to_string()).next());
+    match modified {
+        Some(name) if !force => editor.error = EditorError::NoWriteSinceLastChange(name),
+        _ => editor.is_running = false,
+    }
+}
+
+/// `:w`, writes the buffer to its file or to `args`. An existing file that is not the
+/// buffer's own is only overwritten with `force`, a buffer without a name takes the name
+/// it is written to.
+fn write(editor: &mut Editor, args: &str, force: bool) -> Result<(), EditorError> {
+    let path = match (args.trim(), &editor.file_path) {
+        ("", Some(path)) => path.clone(),
+        ("", None) => return Err(EditorError::NoFileName),
+        (path, _) => path.to_string(),
+    };
+    let own = editor.file_path.as_deref() == Some(path.as_str());
+    if !own && !force && std::path::Path::new(&path).exists() {
+        return Err(EditorError::FileExists(path));
+    }
+    if own && editor.readonly && !force {
+        return Err(EditorError::ReadOnly(path));
+    }
+    editor.finish_loading(editor.buffer);
+    let ending = edit::fileformat_ending(&editor.options.fileformat);
+    let rope = match editor.largefile || editor.hex.is_some() {
+        true => editor.rope.clone(),
+        false => edit::with_line_endings(&editor.rope, ending),
+    };
+    let bytes = crate::commandline::write_to_path(&rope, &path, &editor.options.fileencoding, force)?;
+    if own || editor.file_path.is_none() {
+        editor.file_path = Some(path.clone());
+        editor.modified = false;
+        editor.readonly = false;
+        editor.changes = 0;
+        editor.undo.mark_saved();
+        swap::remove(&path);
+        if !editor.largefile {
+            editor.save_undo(&editor.undo, &rope, &path)?;
+        }
+    }
+    let format = [("utf-8", &editor.options.fileencoding), ("unix", &editor.options.fileformat)]
+        .iter()
+        .filter(|(default, value)| default != value)
+        .map(|(_, value)| format!(" [{}]", value))
+        .collect::<String>();
+    editor.output = match editor.hex {
+        Some(_) => format!("\"{}\" [hex] {}B written", path, bytes),
+        None => format!("\"{}\"{} {}L, {}B written", path, format, editor.last_line() + 1, bytes),
+    };
+    Ok(())
+}
+
+/// `:saveas`, writes the buffer to `path` and makes it the buffer's file. Its swap file moves
+/// along and the filetype is found again for the new name.
+fn save_as(editor: &mut Editor, path: &str, force: bool) -> Result<(), EditorError> {
+    if !force && editor.file_path.as_deref() != Some(path) && std::path::Path::new(path).exists() {
+        return Err(EditorError::FileExists(path.to_string()));
+    }
+    let old = editor.file_path.replace(path.to_string());
+    let renamed = old.as_deref() != Some(path);
+    if let Err(error) = write(editor, "", force || renamed) {
+        editor.file_path = old;
+        return Err(error);
+    }
+    if let Some(old) = old.filter(|_| renamed) {
+        swap::remove(&old);
+    }
+    editor.options.filetype = filetype::detect(path);
+    editor.options.pairs = filetype::pairs(&editor.options.filetype).to_string();
+    Ok(())
+}
+
+/// `:wa`, writes every modified buffer to its file.
+fn write_all(editor: &mut Editor) -> Result<(), EditorError> {
+    if editor.modified {
+        write(editor, "", false)?;
+    }
+    let current = editor.buffer;
+    for index in 0..editor.buffers.len() {
+        let buffer = &editor.buffers[index];
+        if buffer.number == current || !buffer.modified {
+            continue;
+        }
+        let path = buffer.file_path.clone().ok_or(EditorError::NoFileName)?;
+        let number = buffer.number;
+        editor.finish_loading(number);
+        let buffer = &editor.buffers[index];
+        let ending = edit::fileformat_ending(&buffer.fileformat);
+        let rope = match buffer.largefile || buffer.hex.is_some() {
+            true => buffer.rope.clone(),
+            false => edit::with_line_endings(&buffer.rope, ending),
+        };
+        crate::commandline::write_to_path(&rope, &path, &buffer.fileencoding, false)?;
+        swap::remove(&path);
+        if !buffer.largefile {
+            editor.save_undo(&buffer.undo, &rope, &path)?;
+        }
+        editor.buffers[index].modified = false;
+        editor.buffers[index].undo.mark_saved();
+    }
+    Ok(())
+}
+
+/// `:split` and `:vsplit`, edits `path` in the new window when one is given.
+fn split_window(editor: &mut Editor, path: &str, vertical: bool) {
+    editor.split_window(vertical);
+    if !path.is_empty() {
+        edit_file(editor, path, true);
+    }
+}
+
+/// Full names of the Ex commands `execute_command` knows, completed with Tab.
+const COMMANDS: &[&str] = &[
+    "abbreviate",
+    "bdelete",
+    "bnext",
+    "bprevious",
+    "buffer",
+    "buffers",
+    "cabbrev",
+    "close",
+    "cunabbrev",
+    "digraphs",
+    "display",
+    "edit",
+    "files",
+    "height",
+    "hex",
+    "iabbrev",
+    "iunabbrev",
+    "lenline",
+    "line",
+    "ls",
+    "only",
+    "q",
+    "qall",
+    "quit",
+    "registers",
+    "saveas",
+    "set",
+    "split",
+    "tabclose",
+    "tabedit",
+    "tabmove",
+    "tabnew",
+    "tabnext",
+    "tabprevious",
+    "unabbreviate",
+    "update",
+    "vsplit",
+    "w",
+    "wall",
+    "wq",
+    "wqall",
+    "write",
+    "xall",
+    "xit",
+];
+
+/// Runs an Ex command typed after `:`.
+pub fn execute_command(editor: &mut Editor, command: &str) {
+    let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+    match name {
+        "q" | "quit" | "q!" | "quit!" if editor.cmdwin.is_some() => {
+            close_cmdwin(editor);
+        }
+        "q" | "quit" | "q!" | "quit!" if editor.windows.len() > 1 => execute_command(editor, "close"),
+        "q" | "quit" | "q!" | "quit!" if editor.tabs.len() > 1 => execute_command(editor, "tabclose"),
+        "q" | "quit" | "qa" | "qall" | "quitall" => quit(editor, false),
+        "q!" | "quit!" | "qa!" | "qall!" | "quitall!" => quit(editor, true),
+        "w" | "write" | "w!" | "write!" => report(write(editor, args, name.ends_with('!')), editor),
+        "up" | "update" if editor.modified => report(write(editor, args, false), editor),
+        "up" | "update" => {}
+        "sav" | "saveas" | "sav!" | "saveas!" if args.trim().is_empty() => editor.error = EditorError::NoFileName,
+        "sav" | "saveas" | "sav!" | "saveas!" => report(save_as(editor, args.trim(), name.ends_with('!')), editor),
+        "wq" | "wq!" | "x" | "xit" | "exit" | "x!" | "xit!" | "exit!" => {
+            let force = name.ends_with('!');
+            let result = match editor.modified || name.starts_with('w') || !args.trim().is_empty() {
+                true => write(editor, args, force),
+                false => Ok(()),
+            };
+            if result.is_ok() {
+                execute_command(editor, if force { "q!" } else { "q" });
+            }
+            report(result, editor);
+        }
+        "wa" | "wall" => report(write_all(editor), editor),
+        "wqa" | "wqall" | "xa" | "xall" => {
+            let result = write_all(editor);
+            if result.is_ok() {
+                quit(editor, false);
+            }
+            report(result, editor);
+        }
+        "tabnew" | "tabe" | "tabedit" => {
+            editor.new_tab();
+            match args.trim() {
+                "" if name == "tabnew" => {
+                    let number = editor.add_buffer(Rope::new(), None);
+                    editor.switch_buffer(number);
+                }
+                "" => {}
+                path => edit_file(editor, path, true),
+            }
+        }
+        "tabclose" | "tabc" => {
+            let index = match args.trim() {
+                "" => Some(editor.tab),
+                arg => arg.parse::<usize>().ok().filter(|n| (1..=editor.tabs.len()).contains(n)).map(|n| n - 1),
+            };
+            match index.map(|index| editor.close_tab(index)) {
+                Some(Ok(())) => {}
+                Some(Err(error)) => editor.error = error,
+                None => editor.error = EditorError::InvalidArgument(args.to_string()),
+            }
+        }
+        "tabmove" | "tabm" => match tab::move_target(args, editor.tab, editor.tabs.len()) {
+            Some(index) => editor.move_tab(index),
+            None => editor.error = EditorError::InvalidArgument(args.to_string()),
+        },
+        "tabnext" | "tabn" => next_tab(editor, args.trim().parse().unwrap_or(0), true),
+        "tabprevious" | "tabp" | "tabNext" | "tabN" => next_tab(editor, args.trim().parse().unwrap_or(1), false),
+        "split" | "sp" => split_window(editor, args.trim(), false),
+        "vsplit" | "vs" => split_window(editor, args.trim(), true),
+        "close" | "clo" => {
+            if let Err(error) = editor.close_window(editor.window) {
+                editor.error = error;
+            }
+        }
+        "only" | "on" => editor.only_window(),
+        "hex" => toggle_hex(editor),
+        "e" | "edit" => edit_file(editor, args.trim(), false),
+        "e!" | "edit!" => edit_file(editor, args.trim(), true),
+        "bnext" | "bn" => next_buffer(editor, args, true, false),
+        "bnext!" | "bn!" => next_buffer(editor, args, true, true),
+        "bprevious" | "bprev" | "bp" | "bNext" | "bN" => next_buffer(editor, args, false, false),
+        "bprevious!" | "bprev!" | "bp!" | "bNext!" | "bN!" => next_buffer(editor, args, false, true),
+        "ls" | "buffers" | "files" => editor.output = editor.list_buffers(),
+        "bdelete" | "bd" => delete_buffer(editor, args, false),
+        "bdelete!" | "bd!" => delete_buffer(editor, args, true),
+        "b" | "buffer" | "b!" | "buffer!" if args.trim().is_empty() => {}
+        "b" | "buffer" | "b!" | "buffer!" => match editor.find_buffer(args.trim()) {
+            Ok(number) => switch_buffer(editor, number, name.ends_with('!')),
+            Err(error) => editor.error = error,
+        },
+        "lenline" => editor.output = end_of_line_without_new_line(editor).to_string(),
+        "height" => editor.output = editor.screen.max_h.to_string(),
+        "line" => editor.output = editor.rope.line(editor.cursor.gy as usize).chars().collect::<String>().trim_end().to_string(),
+        "set" | "se" => {
+            let fileformat = editor.options.fileformat.clone();
+            let fileencoding = editor.options.fileencoding.clone();
+            match editor.options.set(args) {
+                Ok(output) => editor.output = output,
+                Err(error) => editor.error = error,
+            }
+            if editor.options.fileencoding != fileencoding {
+                editor.modified = true;
+            }
+            if editor.options.fileformat != fileformat {
+                let ending = edit::fileformat_ending(&editor.options.fileformat);
+                editor.finish_loading(editor.buffer);
+                let rope = edit::with_line_endings(&editor.rope, ending);
+                *editor.rope_mut() = rope;
+            }
+            load_speller(editor);
+            editor.load_undo();
+        }
+        "abbreviate" | "ab" => abbreviate(editor, &[Mode::Insert, Mode::Command], args),
+        "iabbrev" | "iab" | "ia" => abbreviate(editor, &[Mode::Insert], args),
+        "cabbrev" | "cab" | "ca" => abbreviate(editor, &[Mode::Command], args),
+        "unabbreviate" | "una" => unabbreviate(editor, &[Mode::Insert, Mode::Command], args),
+        "iunabbrev" | "iuna" => unabbreviate(editor, &[Mode::Insert], args),
+        "cunabbrev" | "cuna" => unabbreviate(editor, &[Mode::Command], args),
+        "digraphs" | "dig" if args.trim().is_empty() => {
+            let width = editor.area.max_w.max(1);
+            let list = digraph::list(&editor.digraphs);
+            let columns = (width / 14).max(1);
+            let lines = list.chunks(columns).map(|row| {
+                row.iter().map(|d| format!("{:<14}", d)).collect::<String>().trim_end().to_string()
+            });
+            editor.output = lines.collect::<Vec<String>>().join("\n");
+        }
+        "digraphs" | "dig" => match digraph::parse_definitions(args) {
+            Some(digraphs) => editor.digraphs.extend(digraphs),
+            None => editor.error = EditorError::InvalidArgument(args.to_string()),
+        },
+        "registers" | "reg" | "display" | "di" => {
+            let names = editor.register_names();
+            let list = names
+                .iter()
+                .filter_map(|name| name.chars().next())
+                .filter(|name| args.is_empty() || args.contains(*name))
+                .map(|name| format!("\"{}   {}", name, editor.register(name).unwrap_or_default().replace('\n', "^J")))
+                .collect::<Vec<String>>();
+            editor.output = format!("Type Name Content\n{}", list.join("\n"));
+        }
+        _ => editor.error = EditorError::InvalidCommand(command.to_string()),
+    }
+}
+
+/// Tab and Shift-Tab on the command line, completes the command name or its last argument:
+/// file names, option names, buffer names or register names depending on the command.
+fn complete_command(editor: &mut Editor, step: isize) {
+    if editor.wildmenu.is_none() {
+        let command = editor.command[..editor.command_cursor].to_string();
+        let (name, args) = match command.split_once(' ') {
+            Some(split) => split,
+            None => {
+                let items = complete::matching(COMMANDS, &command);
+                start_wildmenu(editor, Completion::new(items, command.clone(), 0, 0));
+                select_wildmenu(editor, step);
+                return;
+            }
+        };
+        let word = args.rsplit(' ').next().unwrap_or("");
+        let start = command.len() - word.len();
+        let completion = match name {
+            "w" | "write" | "w!" | "write!" | "e" | "edit" | "saveas" | "sav" | "wq" | "x" | "split" | "sp" | "vsplit" | "vs"
+            | "tabnew" | "tabe" | "tabedit" => {
+                let name = word.rsplit('/').next().unwrap_or("");
+                let base = std::env::current_dir().unwrap_or_default();
+                Completion::new(complete::paths(&base, word), name.to_string(), 0, command.len() - name.len())
+            }
+            "set" | "se" => Completion::new(complete::matching(Options::NAMES, word), word.to_string(), 0, start),
+            "b" | "buffer" | "bd" | "bdelete" => {
+                let names = editor.buffers.iter().map(|b| b.name().to_string()).collect::<Vec<String>>();
+                Completion::new(complete::matching(&names, word), word.to_string(), 0, start)
+            }
+            "registers" | "reg" | "display" | "di" => {
+                Completion::new(editor.register_names(), String::new(), 0, command.len())
+            }
+            _ => return,
+        };
+        start_wildmenu(editor, completion);
+    }
+    select_wildmenu(editor, step);
+}
+
+fn start_wildmenu(editor: &mut Editor, completion: Completion) {
+    if !completion.items.is_empty() {
+        editor.wildmenu = Some(completion);
+    }
+}
+
+/// Moves through the wildmenu and puts the selected item on the command line.
+fn select_wildmenu(editor: &mut Editor, step: isize) {
+    if let Some(wildmenu) = editor.wildmenu.as_mut() {
+        wildmenu.select(step);
+        editor.command.replace_range(wildmenu.col..editor.command_cursor, wildmenu.text());
+        editor.command_cursor = wildmenu.col + wildmenu.text().len();
+    }
+}
+
+/// `:abbreviate` and friends, lists the abbreviations when no expansion is given.
+fn abbreviate(editor: &mut Editor, modes: &[Mode], args: &str) {
+    let (local, lhs, rhs) = abbrev::parse_args(args);
+    if rhs.is_empty() {
+        let mut list = editor.local_abbreviations.list(modes, true);
+        list.extend(editor.abbreviations.list(modes, false));
+        list.retain(|line| line.split_whitespace().nth(1).unwrap_or("").starts_with(lhs));
+        editor.output = if list.is_empty() { "No abbreviation found".to_string() } else { list.join("\n") };
+    } else if local {
+        editor.local_abbreviations.define(modes, lhs, rhs);
+    } else {
+        editor.abbreviations.define(modes, lhs, rhs);
+    }
+}
+
+/// `:unabbreviate`, removes `<buffer>` abbreviations or global ones.
+fn unabbreviate(editor: &mut Editor, modes: &[Mode], args: &str) {
+    let (local, lhs, _) = abbrev::parse_args(args);
+    let found = if local {
+        editor.local_abbreviations.remove(modes, lhs)
+    } else {
+        editor.abbreviations.remove(modes, lhs)
+    };
+    if !found {
+        editor.error = EditorError::NoSuchAbbreviation(lhs.to_string());
+    }
+}
+
+/// Replaces the word before the cursor with its abbreviation for the current mode, called
+/// before a non keyword char is typed. Buffer local abbreviations win over global ones.
+fn expand_abbreviation(editor: &mut Editor) {
+    let before = if editor.mode == Mode::Command {
+        editor.command[..editor.command_cursor].to_string()
+    } else {
+        let line = edit::line_text(&editor.rope, editor.cursor.gy as usize);
+        line.chars().take(editor.cursor.gx).collect()
+    };
+    let word = before.chars().rev().take_while(|c| edit::is_keyword(*c)).collect::<Vec<char>>();
+    let word = word.iter().rev().collect::<String>();
+    let rhs = match editor.local_abbreviations.get(&editor.mode, &word) {
+        Some(rhs) => rhs.clone(),
+        None => match editor.abbreviations.get(&editor.mode, &word) {
+            Some(rhs) => rhs.clone(),
+            None => return,
+        },
+    };
+    if editor.mode == Mode::Command {
+        editor.command_cursor -= word.len();
+        editor.command.replace_range(editor.command_cursor..editor.command_cursor + word.len(), "");
+        command_insert(editor, &rhs);
+        return;
+    }
+    let end = editor.rope.line_to_char(editor.cursor.gy as usize) + editor.cursor.gx;
+    let len = word.chars().count();
+    editor.rope_mut().remove(end - len..end);
+    editor.cursor.gx -= len;
+    editor.cursor.x -= len as u16;
+    insert_str_to_rope(editor, &rhs);
+}
+
+/// Keys of the hex view: moving by byte and row, `Tab` between the hex and ASCII columns and
+/// `i` or `R` to overwrite bytes until `Esc`. Other keys in Normal mode go on to the mappings.
+fn hex_key(editor: &mut Editor, key: KeyEvent) -> bool {
+    let mut view = match editor.hex.take() {
+        Some(view) => view,
+        None => return false,
+    };
+    let (len, rows) = (editor.rope.len_chars(), editor.screen.max_h);
+    let insert = editor.mode == Mode::Insert;
+    let row_start = view.offset - view.offset % hex::WIDTH;
+    let mut handled = true;
+    match (key.code, insert) {
+        (KeyCode::Left, _) | (KeyCode::Char('h'), false) | (KeyCode::Backspace, _) => {
+            view.goto(view.offset.saturating_sub(1), len, insert, rows)
+        }
+        (KeyCode::Right, _) | (KeyCode::Char('l'), false) => view.goto(view.offset + 1, len, insert, rows),
+        (KeyCode::Up, _) | (KeyCode::Char('k'), false) => {
+            view.goto(view.offset.saturating_sub(hex::WIDTH), len, insert, rows)
+        }
+        (KeyCode::Down, _) | (KeyCode::Char('j'), false) => view.goto(view.offset + hex::WIDTH, len, insert, rows),
+        (KeyCode::PageUp, _) => view.goto(view.offset.saturating_sub(hex::WIDTH * rows), len, insert, rows),
+        (KeyCode::PageDown, _) => view.goto(view.offset + hex::WIDTH * rows, len, insert, rows),
+        (KeyCode::Home, _) | (KeyCode::Char('0'), false) if editor.count == 0 => {
+            view.goto(row_start, len, insert, rows)
+        }
+        (KeyCode::End, _) | (KeyCode::Char('$'), false) => view.goto(row_start + hex::WIDTH - 1, len, insert, rows),
+        (KeyCode::Char('G'), false) => view.goto(len, len, false, rows),
+        (KeyCode::Tab, _) => {
+            view.ascii = !view.ascii;
+            view.low = false;
+        }
+        (KeyCode::Char('i') | KeyCode::Char('R'), false) => editor.mode = Mode::Insert,
+        (KeyCode::Esc, true) => {
+            editor.mode = Mode::Normal;
+            view.goto(view.offset, len, false, rows);
+        }
+        (KeyCode::Char('x'), false) | (KeyCode::Delete, false) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+            let count = editor.count_or(1);
+            view.delete(editor.rope_mut(), count, rows);
+            editor.count = 0;
+        }
+        (KeyCode::Char(c), true) => overwrite_byte(editor, &mut view, c),
+        (_, insert) => handled = insert || !hex_passes(editor, &key),
+    }
+    editor.hex = Some(view);
+    handled
+}
+
+/// Normal mode keys that work the same in the hex view: commands, search, undo, windows and
+/// counts. The others are ignored, they would edit the text at the cursor of the text.
+fn hex_passes(editor: &Editor, key: &KeyEvent) -> bool {
+    match (key.code, key.modifiers.contains(KeyModifiers::CONTROL)) {
+        (KeyCode::Char(':' | '/' | '?' | 'n' | 'N' | 'u' | 'q' | '1'..='9'), false) | (KeyCode::Esc, _) => true,
+        (KeyCode::Char('0'), false) => editor.count > 0,
+        (KeyCode::Char('r' | 'w' | 'c' | '^'), true) => true,
+        _ => false,
+    }
+}
+
+/// Typing `c` over the byte at the cursor of the hex view: a hex digit sets half of it in the
+/// hex column and any char up to `\u{ff}` sets all of it in the ASCII column. Typing past the
+/// last byte adds one.
+fn overwrite_byte(editor: &mut Editor, view: &mut hex::HexView, c: char) {
+    let old = match view.offset < editor.rope.len_chars() {
+        true => editor.rope.char(view.offset) as u32 as u8,
+        false => 0,
+    };
+    let (byte, next) = match (view.ascii, c.to_digit(16)) {
+        (true, _) if (c as u32) < 0x100 => (c as u32 as u8, true),
+        (false, Some(digit)) => (hex::set_nibble(old, digit as u8, view.low), view.low),
+        _ => return,
+    };
+    if view.offset < editor.rope.len_chars() {
+        editor.rope_mut().remove(view.offset..view.offset + 1);
+    }
+    editor.rope_mut().insert_char(view.offset, byte as char);
+    match next {
+        true => view.goto(view.offset + 1, editor.rope.len_chars(), true, editor.screen.max_h),
+        false => view.low = true,
+    }
+}
+
+/// `:hex`, shows the buffer as hex bytes or as text again. The bytes are the text written in
+/// `fileencoding` and are read back in the encoding found in them, undo starts over.
+fn toggle_hex(editor: &mut Editor) {
+    editor.finish_loading(editor.buffer);
+    let text = editor.rope.to_string();
+    let (encoding, text) = match editor.hex.take() {
+        Some(view) => match crate::encoding::encode_chunk(&text, "latin1") {
+            Ok(bytes) => {
+                let encoding = crate::encoding::detect(&bytes);
+                (encoding, crate::encoding::decode(&bytes, encoding))
+            }
+            Err(c) => {
+                editor.hex = Some(view);
+                return editor.error = EditorError::InvalidArgument(format!("{:?} is not a byte", c));
+            }
+        },
+        None => match crate::encoding::encode_chunk(&text, &editor.options.fileencoding) {
+            Ok(bytes) => {
+                let bom = crate::encoding::bom(&editor.options.fileencoding);
+                editor.hex = Some(hex::HexView::default());
+                ("latin1", crate::encoding::decode_chunk(&[bom, &bytes].concat(), "latin1"))
+            }
+            Err(c) => {
+                let encoding = &editor.options.fileencoding;
+                return editor.error = EditorError::InvalidArgument(format!("{:?} cannot be written in {}", c, encoding));
+            }
+        },
+    };
+    editor.options.fileencoding = encoding.to_string();
+    editor.rope = Rope::from_str(&text);
+    if editor.hex.is_none() {
+        editor.options.fileformat = edit::detect_fileformat(&editor.rope).to_string();
+    }
+    editor.undo.clear();
+    editor.goto(0, 0);
+}
+
 pub fn key_builder() -> Mapper {
     Mapper::new()
         .build_normal()
         .build_insert()
         .build_command()
+        .build_visual()
+}
+
+/// Turns a key string like `gJ`, `g<C-a>` or `<C-w>h` into key events. Upper case
+/// letters carry the shift modifier the same way `key_adder` maps them.
+pub fn parse_keys(keys: &str) -> Vec<KeyEvent> {
+    let mut events = Vec::new();
+    let mut rest = keys;
+    while let Some(c) = rest.chars().next() {
+        let name = rest[1..]
+            .find('>')
+            .map(|end| &rest[1..end + 1])
+            .filter(|name| c == '<' && name.len() > 1 && !name.contains('<'));
+        let name = match name {
+            Some(name) => name,
+            None => {
+                events.push(char_key(c, KeyModifiers::NONE));
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+        };
+        rest = &rest[name.len() + 2..];
+        let (modifier, name) = match name.get(..2) {
+            Some("C-") => (KeyModifiers::CONTROL, &name[2..]),
+            Some("S-") => (KeyModifiers::SHIFT, &name[2..]),
+            Some("A-") => (KeyModifiers::ALT, &name[2..]),
+            _ => (KeyModifiers::NONE, name),
+        };
+        let code = match name {
+            "Esc" => KeyCode::Esc,
+            "CR" => KeyCode::Enter,
+            "Tab" if modifier == KeyModifiers::SHIFT => KeyCode::BackTab,
+            "Tab" => KeyCode::Tab,
+            "BS" => KeyCode::Backspace,
+            "Del" => KeyCode::Delete,
+            "Up" => KeyCode::Up,
+            "Down" => KeyCode::Down,
+            "Left" => KeyCode::Left,
+            "Right" => KeyCode::Right,
+            "Home" => KeyCode::Home,
+            "End" => KeyCode::End,
+            "lt" => KeyCode::Char('<'),
+            name => {
+                events.push(char_key(name.chars().next().unwrap_or(' '), modifier));
+                continue;
+            }
+        };
+        events.push(KeyEvent::new(code, modifier));
+    }
+    events
+}
+
+fn char_key(c: char, modifier: KeyModifiers) -> KeyEvent {
+    if c.is_ascii_uppercase() {
+        KeyEvent::new(KeyCode::Char(c), modifier | KeyModifiers::SHIFT)
+    } else {
+        KeyEvent::new(KeyCode::Char(c), modifier)
+    }
+}
+
+fn cursor_down(editor: &mut Editor) {
+    if editor.cursor.y != editor.screen.max_h.saturating_sub(1) as u16 {
+        // This is for moving cursor
+        editor.cursor.y = (editor.cursor.y + 1).min(
+            (std::cmp::min(editor.screen.bottom(), editor.rope.len_lines().saturating_sub(2)))
+                as u16);
+
+        editor.cursor.gy = (editor.cursor.gy + 1).min(editor.rope.len_lines().saturating_sub(2) as u32);
+    } else {
+        // This is for scrolling
+        editor.screen.t = (editor.screen.t + 1).min(
+            std::cmp::max(editor.screen.bottom(), editor.rope.len_lines().saturating_sub(2)));
+
+        editor.cursor.gy = (editor.cursor.gy + 1).min(editor.rope.len_lines().saturating_sub(2) as u32);
+    }
+    editor.cursor.x = std::cmp::min(end_of_line_without_new_line(editor), editor.cursor.max_x) as u16;
+    editor.cursor.gx = std::cmp::min(end_of_line_without_new_line(editor), editor.cursor.max_x);
+}
+
+fn cursor_up(editor: &mut Editor) {
+    if editor.cursor.y != 0 {
+        // This is for moving cursor
+        editor.cursor.y = editor.cursor.y.saturating_sub(1);
+        editor.cursor.gy = editor.cursor.gy.saturating_sub(1);
+    } else {
+        // This is for scrolling
+        editor.screen.t = editor.screen.t.saturating_sub(1);
+        editor.cursor.gy = editor.cursor.gy.saturating_sub(1);
+    }
+    editor.cursor.x = std::cmp::min(end_of_line_without_new_line(editor), editor.cursor.max_x) as u16;
+    editor.cursor.gx = std::cmp::min(end_of_line_without_new_line(editor), editor.cursor.max_x);
+}
+
+fn cursor_left(editor: &mut Editor) {
+    editor.cursor.x = editor.cursor.x.saturating_sub(1);
+    editor.cursor.gx = editor.cursor.gx.saturating_sub(1);
+    editor.cursor.max_x = std::cmp::min(editor.cursor.gx, editor.cursor.max_x);
+}
+
+fn cursor_right(editor: &mut Editor) {
+    editor.cursor.x = editor.cursor.x.saturating_add(1).min(end_of_line_without_new_line(editor) as u16);
+    editor.cursor.gx = editor.cursor.gx.saturating_add(1).min(end_of_line_without_new_line(editor));
+    editor.cursor.max_x = std::cmp::max(editor.cursor.x as usize, editor.cursor.max_x);
+}
+
+fn start_visual(editor: &mut Editor, linewise: bool) {
+    editor.mode = Mode::Visual;
+    editor.anchor = (editor.cursor.gx, editor.cursor.gy as usize);
+    editor.linewise = linewise;
+}
+
+/// Adds `delta` to the number under or after `col` on `line` and returns the column of
+/// its last char.
+fn add_to_number(editor: &mut Editor, line: usize, col: usize, delta: i64) -> Option<usize> {
+    let text = edit::line_text(&editor.rope, line);
+    let (new, col) = increment::increment(&text, col, delta, &editor.options.nrformats)?;
+    edit::replace_line(editor.rope_mut(), line, &new);
+    Some(col)
+}
+
+/// `Ctrl-A` and `Ctrl-X` on a visual selection change the first selected number of every line,
+/// `progressive` makes each changed line add one more count than the one before it.
+fn add_to_selection(editor: &mut Editor, sign: i64, progressive: bool) {
+    let step = editor.count_or(1) as i64 * sign;
+    let range = editor.visual_range();
+    let (first, last) = editor.visual_lines();
+    let mut delta = step;
+    for line in first..=last {
+        // Only the selected columns count on the first and last line of a charwise selection.
+        let line_start = editor.rope.line_to_char(line);
+        let (start, end) = (range.start.saturating_sub(line_start), range.end.saturating_sub(line_start));
+        let text = edit::line_text(&editor.rope, line);
+        if let Some((new, _)) = increment::increment_in(&text, start, end, delta, &editor.options.nrformats) {
+            edit::replace_line(editor.rope_mut(), line, &new);
+            if progressive {
+                delta += step;
+            }
+        }
+    }
+    let col = range.start - editor.rope.line_to_char(first);
+    editor.goto(first, col);
+    editor.mode = Mode::Normal;
+}
+
+/// Line of the next blank line in `direction` for `{` and `}`, or the first or last line.
+fn next_blank_line(editor: &Editor, direction: isize) -> usize {
+    let mut line = editor.cursor.gy as usize;
+    loop {
+        match line.checked_add_signed(direction) {
+            Some(next) if next <= editor.last_line() => line = next,
+            _ => return line,
+        }
+        if edit::line_text(&editor.rope, line).trim().is_empty() {
+            return line;
+        }
+    }
+}
+
+/// Re-wraps `start..=end` at `textwidth` and leaves the cursor on the last formatted line.
+fn format_lines(editor: &mut Editor, start: usize, end: usize) {
+    let lines = (start..=end).map(|line| edit::line_text(&editor.rope, line)).collect::<Vec<String>>();
+    let formatted = format::format_lines(&lines, editor.options.textwidth, &editor.options.filetype);
+    edit::replace_lines(editor.rope_mut(), start, end, &formatted);
+    editor.goto(start + formatted.len() - 1, 0);
+}
+
+/// Breaks the line being typed at the last blank before `textwidth`, keeping its indent
+/// and comment marker. `formatoptions` decides if text (`t`) and comments (`c`) wrap.
+fn auto_wrap(editor: &mut Editor) {
+    let line = editor.cursor.gy as usize;
+    let text = edit::line_text(&editor.rope, line);
+    let leader = format::split_leader(&text, &editor.options.filetype).0;
+    let flag = if leader.trim().is_empty() { 't' } else { 'c' };
+    if !editor.options.formatoptions.contains(flag) {
+        return;
+    }
+    if let Some(range) = format::wrap_point(&text, editor.options.textwidth, &editor.options.filetype) {
+        let start = editor.rope.line_to_char(line);
+        let ending = edit::fileformat_ending(&editor.options.fileformat);
+        editor.rope_mut().remove(start + range.start..start + range.end);
+        editor.rope_mut().insert(start + range.start, &format!("{}{}", ending, leader));
+        let col = editor.cursor.gx.saturating_sub(range.end) + leader.chars().count();
+        editor.goto(line + 1, col);
+    }
+}
+
+/// `Ctrl-N` and `Ctrl-P`, starts completing the keyword before the cursor from the words
+/// in the buffers or moves through the open popup menu.
+fn complete_keyword(editor: &mut Editor, step: isize) {
+    if editor.completion.is_none() {
+        let line = editor.cursor.gy as usize;
+        let before = edit::line_text(&editor.rope, line).chars().take(editor.cursor.gx).collect::<Vec<char>>();
+        let prefix = before.iter().rev().take_while(|c| edit::is_keyword(**c)).collect::<Vec<&char>>();
+        let prefix = prefix.into_iter().rev().collect::<String>();
+        let col = before.len() - prefix.chars().count();
+        let index = editor.rope.line_to_char(line) + col;
+        let mut ropes = vec![&editor.rope];
+        ropes.extend(editor.buffers.iter().filter(|b| b.number != editor.buffer && !b.largefile).map(|b| &b.rope));
+        let items = complete::keywords(&ropes, &prefix, index);
+        start_completion(editor, Completion::new(items, prefix, line, col));
+    }
+    select_completion(editor, step);
+}
+
+/// `Ctrl-X Ctrl-F`, completes the file name before the cursor. Relative paths start from the
+/// directory of the file being edited or the working directory when there is none.
+fn complete_path(editor: &mut Editor) {
+    let line = editor.cursor.gy as usize;
+    let before = edit::line_text(&editor.rope, line).chars().take(editor.cursor.gx).collect::<Vec<char>>();
+    let typed = before.iter().rev().take_while(|c| complete::is_path_char(**c)).collect::<Vec<&char>>();
+    let typed = typed.into_iter().rev().collect::<String>();
+    let name = typed.rsplit('/').next().unwrap_or("").to_string();
+    let col = before.len() - name.chars().count();
+    let base = editor
+        .file_path
+        .as_ref()
+        .and_then(|path| std::path::Path::new(path).parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| std::env::current_dir().unwrap_or_default());
+    let items = complete::paths(&base, &typed);
+    start_completion(editor, Completion::new(items, name, line, col));
+}
+
+/// Opens the popup menu, or shows an error when there is nothing to complete.
+fn start_completion(editor: &mut Editor, completion: Completion) {
+    if completion.items.is_empty() {
+        editor.error = EditorError::PatternNotFound(completion.original);
+    } else {
+        editor.error = EditorError::NONE;
+        editor.completion = Some(completion);
+    }
+}
+
+/// Moves through the popup menu and puts the selected item in the text.
+fn select_completion(editor: &mut Editor, step: isize) {
+    if let Some(completion) = editor.completion.as_mut() {
+        let old = completion.text().to_string();
+        completion.select(step);
+        let new = completion.text().to_string();
+        replace_completed(editor, &old, &new);
+    }
+}
+
+/// Removes `old` from before the cursor and types `new` in its place.
+fn replace_completed(editor: &mut Editor, old: &str, new: &str) {
+    let len = old.chars().count();
+    let end = editor.rope.line_to_char(editor.cursor.gy as usize) + editor.cursor.gx;
+    editor.rope_mut().remove(end - len..end);
+    editor.cursor.gx -= len;
+    editor.cursor.x -= len as u16;
+    insert_str_to_rope(editor, new);
+}
+
+/// Loads the dictionary when `spell` was turned on, or turns it off again when that fails.
+fn load_speller(editor: &mut Editor) {
+    if !editor.options.spell {
+        editor.speller = None;
+        return;
+    }
+    if editor.speller.is_none() {
+        match Speller::load(&editor.options.dictionary, &editor.options.spellfile) {
+            Ok(speller) => editor.speller = Some(speller),
+            Err(error) => {
+                editor.options.spell = false;
+                editor.error = EditorError::Io(format!("{}: {}", editor.options.dictionary, error));
+            }
+        }
+    }
+}
+
+/// Misspelled words on `line` as char ranges.
+fn misspelled_on(editor: &Editor, line: usize) -> Vec<std::ops::Range<usize>> {
+    match &editor.speller {
+        Some(speller) => {
+            let comment = crate::filetype::spell_comment(&editor.options.filetype);
+            spell::misspelled(speller, &edit::line_text(&editor.rope, line), comment)
+        }
+        None => Vec::new(),
+    }
+}
+
+/// `]s` and `[s`, moves to the next or previous misspelled word and wraps around the file.
+fn next_misspelled(editor: &mut Editor, forward: bool) {
+    if editor.speller.is_none() {
+        return;
+    }
+    let (col, line) = (editor.cursor.gx, editor.cursor.gy as usize);
+    let lines = editor.last_line() + 1;
+    for step in 0..=lines {
+        let current = if forward { (line + step) % lines } else { (line + lines - step % lines) % lines };
+        let words = misspelled_on(editor, current);
+        let found = match (forward, step) {
+            (true, 0) => words.iter().find(|word| word.start > col),
+            (false, 0) => words.iter().rev().find(|word| word.start < col),
+            (true, _) => words.first(),
+            (false, _) => words.last(),
+        };
+        if let Some(word) = found {
+            return editor.goto(current, word.start);
+        }
+    }
+}
+
+/// Misspelled word under the cursor as its start column and text.
+fn misspelled_at_cursor(editor: &Editor) -> Option<(usize, String)> {
+    let (col, line) = (editor.cursor.gx, editor.cursor.gy as usize);
+    let word = misspelled_on(editor, line).into_iter().find(|word| word.contains(&col))?;
+    let text = edit::line_text(&editor.rope, line).chars().skip(word.start).take(word.len()).collect();
+    Some((word.start, text))
+}
+
+/// `z=`, lists suggestions for the misspelled word under the cursor. With a count the
+/// suggestion is used right away, otherwise the next digit typed picks one.
+fn suggest_spelling(editor: &mut Editor) {
+    let (col, word) = match misspelled_at_cursor(editor) {
+        Some(found) => found,
+        None => return,
+    };
+    let suggestions = editor.speller.as_ref().map(|speller| speller.suggest(&word)).unwrap_or_default();
+    let line = editor.cursor.gy as usize;
+    if editor.count > 0 {
+        if let Some(choice) = suggestions.get(editor.count - 1) {
+            replace_word(editor, line, col, &choice.clone());
+        }
+        return;
+    }
+    if suggestions.is_empty() {
+        editor.error = EditorError::PatternNotFound(word);
+        return;
+    }
+    let list = suggestions.iter().enumerate().map(|(i, s)| format!("{} \"{}\"", i + 1, s));
+    editor.output = format!("Change \"{}\" to:\n{}", word, list.collect::<Vec<String>>().join("\n"));
+    editor.pending = Some(Pending::Suggestion(suggestions, (col, line)));
+}
+
+/// Replaces the word starting at `col` on `line` with `new`.
+fn replace_word(editor: &mut Editor, line: usize, col: usize, new: &str) {
+    let text = edit::line_text(&editor.rope, line).chars().skip(col).collect::<String>();
+    let len = text.chars().take_while(|c| c.is_alphabetic() || *c == '\'').count();
+    let start = editor.rope.line_to_char(line) + col;
+    editor.rope_mut().remove(start..start + len);
+    editor.rope_mut().insert(start, new);
+    editor.goto(line, col);
+}
+
+/// `zg` and `zw`, adds the word under the cursor to the spellfile as good or wrong.
+fn add_spelling(editor: &mut Editor, good: bool) {
+    let line = edit::line_text(&editor.rope, editor.cursor.gy as usize).chars().collect::<Vec<char>>();
+    let col = editor.cursor.gx;
+    if !line.get(col).is_some_and(|c| c.is_alphabetic()) {
+        return;
+    }
+    let start = col - line[..col].iter().rev().take_while(|c| c.is_alphabetic()).count();
+    let word = line[start..].iter().take_while(|c| c.is_alphabetic()).collect::<String>();
+    let spellfile = editor.options.spellfile.clone();
+    if let Some(speller) = editor.speller.as_mut() {
+        if let Err(error) = speller.add(&word, good, &spellfile) {
+            editor.error = EditorError::Io(format!("{}: {}", spellfile, error));
+        }
+    }
+}
+
+/// `J` and `gJ`, puts the cursor on the last join point.
+fn join(editor: &mut Editor, line: usize, count: usize, spaces: bool) {
+    if line >= editor.last_line() {
+        return;
+    }
+    if let Some(col) = edit::join_lines(editor.rope_mut(), line, count, spaces) {
+        editor.goto(line, col);
+    }
 }
 
 fn insert_char_to_rope(editor: &mut Editor, c: char) {
-    let line_index = editor.rope.line_to_char(editor.cursor.y as usize);
-    editor
-        .rope
-        .insert_char(line_index + editor.cursor.x as usize, c);
+    let index = editor.rope.line_to_char(editor.cursor.gy as usize) + editor.cursor.gx;
+    editor.rope_mut().insert_char(index, c);
     editor.cursor.x += 1;
+    editor.cursor.gx += 1;
 }
 
-fn insert_str_to_rope(editor: &mut Editor, s: &str) {
-    for c in s.chars() {
+/// A char typed in Insert mode, it may expand an abbreviation, close a pair or wrap the line.
+fn insert_typed(editor: &mut Editor, c: char) {
+    if !edit::is_keyword(c) {
+        expand_abbreviation(editor);
+    }
+    if !auto_pair(editor, c) {
         insert_char_to_rope(editor, c);
     }
+    auto_wrap(editor);
+}
+
+/// A char typed on the command line, it may expand an abbreviation.
+fn command_typed(editor: &mut Editor, c: char) {
+    if !edit::is_keyword(c) {
+        expand_abbreviation(editor);
+    }
+    command_insert(editor, &c.to_string());
+}
+
+/// Puts a digraph or literal char in the text or on the command line as it is.
+fn insert_literal(editor: &mut Editor, c: char) {
+    match editor.mode {
+        Mode::Command => command_insert(editor, &c.to_string()),
+        _ => insert_char_to_rope(editor, c),
+    }
+}
+
+/// Feeds a key to a `Ctrl-K`, `Ctrl-V` or `z=` waiting for its chars. Returns false when the key
+/// still has to be handled as usual.
+fn pending_input(editor: &mut Editor, key: KeyEvent) -> bool {
+    let c = match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            char::from_u32(c.to_ascii_uppercase() as u32 ^ 0x40).unwrap_or(c)
+        }
+        KeyCode::Char(c) => c,
+        KeyCode::Tab => '\t',
+        KeyCode::Enter => '\r',
+        KeyCode::Esc => '\u{1b}',
+        KeyCode::Backspace => '\u{8}',
+        _ => {
+            // The swap file question only goes away with one of its answers.
+            if !matches!(editor.pending, Some(Pending::Swap(_))) {
+                editor.pending = None;
+            }
+            return true;
+        }
+    };
+    match editor.pending.take() {
+        Some(Pending::Swap(swap)) if key.code == KeyCode::Esc => answer_recovery(editor, swap, 'q'),
+        Some(Pending::Swap(swap)) if "rRoOdDeEqQaA".contains(c) => answer_recovery(editor, swap, c),
+        Some(Pending::Swap(swap)) => offer_recovery(editor, swap),
+        Some(Pending::Suggestion(suggestions, (col, line))) => {
+            editor.output.clear();
+            let choice = c.to_digit(10).filter(|n| *n > 0).and_then(|n| suggestions.get(n as usize - 1));
+            if let Some(word) = choice {
+                replace_word(editor, line, col, word);
+            }
+        }
+        Some(Pending::Digraph(_)) if key.code == KeyCode::Esc => {}
+        Some(Pending::Digraph(None)) => editor.pending = Some(Pending::Digraph(Some(c))),
+        Some(Pending::Digraph(Some(first))) => {
+            let digraph = digraph::lookup(&editor.digraphs, first, c).unwrap_or(c);
+            insert_literal(editor, digraph);
+        }
+        Some(Pending::Literal(mut typed)) => {
+            typed.push(c);
+            match digraph::literal(&typed) {
+                Literal::Pending => editor.pending = Some(Pending::Literal(typed)),
+                Literal::Done(c) => insert_literal(editor, c),
+                Literal::DoneBefore(c) => {
+                    insert_literal(editor, c);
+                    return false;
+                }
+                Literal::Invalid => {}
+            }
+        }
+        None => return false,
+    }
+    true
+}
+
+/// Chars before and after the cursor on its line.
+fn chars_around_cursor(editor: &Editor) -> (Option<char>, Option<char>) {
+    let line = edit::line_text(&editor.rope, editor.cursor.gy as usize).chars().collect::<Vec<char>>();
+    let col = editor.cursor.gx;
+    (col.checked_sub(1).and_then(|i| line.get(i)).copied(), line.get(col).copied())
+}
+
+/// Types `c` with `autopairs`, returns false when it should be typed as usual.
+fn auto_pair(editor: &mut Editor, c: char) -> bool {
+    if !editor.options.autopairs {
+        return false;
+    }
+    let (before, after) = chars_around_cursor(editor);
+    match pairs::on_char(&editor.options.pairs, c, before, after) {
+        PairAction::Pair(close) => {
+            insert_char_to_rope(editor, c);
+            insert_char_to_rope(editor, close);
+            editor.goto(editor.cursor.gy as usize, editor.cursor.gx - 1);
+            true
+        }
+        PairAction::Skip => {
+            editor.goto(editor.cursor.gy as usize, editor.cursor.gx + 1);
+            true
+        }
+        PairAction::Type => false,
+    }
+}
+
+/// Backspace between an empty pair deletes both chars.
+fn delete_pair(editor: &mut Editor) -> bool {
+    if !editor.options.autopairs {
+        return false;
+    }
+    match chars_around_cursor(editor) {
+        (Some(open), Some(close)) if pairs::closer(&editor.options.pairs, open) == Some(close) => {
+            let col = editor.cursor.gx;
+            let start = editor.rope.line_to_char(editor.cursor.gy as usize) + col;
+            editor.rope_mut().remove(start - 1..start + 1);
+            editor.goto(editor.cursor.gy as usize, col - 1);
+            true
+        }
+        _ => false,
+    }
 }
 
-pub(crate) fn end_of_line_without_new_line(editor: &Editor) -> u16 {
+/// Enter between `{` and `}` puts the closer on its own line and the cursor on an indented
+/// line between them.
+fn open_block(editor: &mut Editor) -> bool {
+    if !editor.options.autopairs || chars_around_cursor(editor) != (Some('{'), Some('}')) {
+        return false;
+    }
+    let line = editor.cursor.gy as usize;
+    let text = edit::line_text(&editor.rope, line);
+    let indent = &text[..text.len() - text.trim_start().len()];
+    let ending = edit::fileformat_ending(&editor.options.fileformat);
+    let inner = format!("{}    ", indent);
+    let index = editor.rope.line_to_char(line) + editor.cursor.gx;
+    editor.rope_mut().insert(index, &format!("{}{}{}{}", ending, inner, ending, indent));
+    editor.goto(line + 1, inner.chars().count());
+    true
+}
+
+/// Backspace in Insert mode, at the start of a line it joins it with the line above.
+fn backspace(editor: &mut Editor) {
+    let (col, line) = (editor.cursor.gx, editor.cursor.gy as usize);
+    if col > 0 {
+        return delete_back_to(editor, col - 1);
+    }
+    if line == 0 {
+        return;
+    }
+    let end = edit::line_len(&editor.rope, line - 1);
+    let start = editor.rope.line_to_char(line - 1) + end;
+    let next = editor.rope.line_to_char(line);
+    editor.rope_mut().remove(start..next);
+    editor.goto(line - 1, end);
+}
+
+/// Deletes the text between `col` and the cursor on the cursor line.
+fn delete_back_to(editor: &mut Editor, col: usize) {
+    let line = editor.cursor.gy as usize;
+    let start = editor.rope.line_to_char(line);
+    let end = start + editor.cursor.gx;
+    if start + col < end {
+        editor.rope_mut().remove(start + col..end);
+    }
+    editor.goto(line, col);
+}
+
+/// Inserts `s` at the cursor and leaves the cursor after it, on the last line of `s` when it
+/// holds line breaks.
+fn insert_str_to_rope(editor: &mut Editor, s: &str) {
+    if s.is_empty() {
+        return;
+    }
+    let line = editor.cursor.gy as usize;
+    let start = editor.rope.line_to_char(line) + editor.cursor.gx;
+    editor.rope_mut().insert(start, s);
+    let end = start + s.chars().count();
+    let last = editor.rope.char_to_line(end);
+    editor.goto(last, end - editor.rope.line_to_char(last));
+}
+
+pub(crate) fn end_of_line_without_new_line(editor: &Editor) -> usize {
     let line = editor
         .rope
         .line(editor.cursor.gy as usize)
@@ -316,7 +2222,7 @@ pub(crate) fn end_of_line_without_new_line(editor: &Editor) -> u16 {
         .to_string();
     let len = line.len().saturating_sub(1);
     let tabs = line.count_char('\t') * 3;
-    (len + tabs) as u16
+    len + tabs
 
 }
 
@@ -351,3 +2257,57 @@ fn test_line_len_rope_keymapper() {
     println!("{}", v.iter().collect::<String>());
     assert_eq!(v.len(), 13);
 }
+
+#[test]
+fn test_saveas_and_undo_to_written_text() {
+    let dir = std::env::temp_dir().join(format!("revim-saveas-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (old, new) = (dir.join("notes.txt").display().to_string(), dir.join("notes.rs").display().to_string());
+    let mut editor = Editor::new(Rope::from_str("one\n"), Some(old.clone()));
+    editor.options.undofile = true;
+    editor.options.undodir = dir.join("undo").display().to_string();
+    let mapper = key_builder();
+    for key in parse_keys("ia<Esc>") {
+        mapper.dispatch(&mut editor, key);
+    }
+    editor.write_swap();
+    assert!(swap::find(&old).is_none() && dir.join(".notes.txt.swp").exists());
+    execute_command(&mut editor, &format!("saveas {}", new));
+    assert_eq!(std::fs::read_to_string(&new).unwrap(), "aone\n");
+    assert!(!dir.join(".notes.txt.swp").exists());
+    assert!(crate::undo::undo_path(&dir.join("undo"), &new).exists());
+    assert_eq!((editor.file_path.as_deref(), editor.options.filetype.as_str()), (Some(new.as_str()), "rust"));
+    // Undo back to the text just written leaves the buffer unmodified.
+    for key in parse_keys("ib<Esc>") {
+        mapper.dispatch(&mut editor, key);
+    }
+    assert!(editor.modified);
+    mapper.dispatch(&mut editor, parse_keys("u")[0]);
+    assert!(!editor.modified);
+    mapper.dispatch(&mut editor, parse_keys("u")[0]);
+    assert_eq!((editor.rope.to_string().as_str(), editor.modified), ("one\n", true));
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_swap_question_waits_for_an_answer() {
+    let dir = std::env::temp_dir().join(format!("revim-recover-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("notes.txt").display().to_string();
+    std::fs::write(dir.join(".notes.txt.swp"), "revim-swap 4294967295\nlost\n").unwrap();
+    let mut editor = Editor::new(Rope::from_str("one\n"), None);
+    let mapper = key_builder();
+    execute_command(&mut editor, &format!("e {}", file));
+    assert!(matches!(editor.pending, Some(Pending::Swap(_))));
+    for key in parse_keys("<Right><CR>x") {
+        mapper.dispatch(&mut editor, key);
+    }
+    assert!(matches!(editor.pending, Some(Pending::Swap(_))));
+    assert_eq!(editor.rope.len_chars(), 0);
+    // Esc quits editing the file and goes back to the buffer before it.
+    mapper.dispatch(&mut editor, parse_keys("<Esc>")[0]);
+    assert!(editor.pending.is_none() && editor.is_running);
+    assert_eq!((editor.buffer, editor.buffers.len(), editor.rope.to_string().as_str()), (1, 1, "one\n"));
+    assert!(dir.join(".notes.txt.swp").exists());
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,114 @@
+use crate::encoding;
+use std::fs::File;
+use std::io::{self, Read};
+
+/// Bytes read before the first screen is shown, enough for any terminal.
+const FIRST_CHUNK: usize = 1 << 20;
+/// Bytes read each time the editor has no key to handle.
+const CHUNK: usize = 4 << 20;
+
+/// Reads the rest of a file in large file mode into buffer `buffer` a chunk at a time, while
+/// the editor keeps handling keys.
+#[derive(Debug)]
+pub struct Loader {
+    pub buffer: usize,
+    file: File,
+    encoding: &'static str,
+    /// Bytes of a char cut in half by the end of the last chunk.
+    rest: Vec<u8>,
+    read: u64,
+    size: u64,
+    /// Bytes not valid in the encoding were read, and replaced, since this was last taken.
+    pub replaced: bool,
+}
+
+impl Loader {
+    /// Opens `path` when it is bigger than `megabytes` and reads the first chunk, returning its
+    /// text, the encoding and the loader for the rest. `None` when the file is small enough
+    /// to read at once.
+    pub fn open(path: &str, megabytes: usize, encoding: Option<&'static str>) -> io::Result<Option<(String, &'static str, Self)>> {
+        let size = std::fs::metadata(path)?.len();
+        if megabytes == 0 || size <= megabytes as u64 * (1 << 20) {
+            return Ok(None);
+        }
+        let file = File::open(path)?;
+        let mut loader = Self { buffer: 0, file, encoding: "utf-8", rest: Vec::new(), read: 0, size, replaced: false };
+        let bytes = loader.read_bytes(FIRST_CHUNK)?;
+        let encoding = encoding.unwrap_or_else(|| encoding::detect(&bytes[..encoding::complete_len(&bytes, "utf-8")]));
+        loader.encoding = encoding;
+        let bytes = bytes.strip_prefix(encoding::bom(encoding)).unwrap_or(&bytes);
+        Ok(Some((loader.decode(bytes.to_vec()), encoding, loader)))
+    }
+
+    /// Text of the next chunk, `None` once the whole file is read.
+    pub fn next_chunk(&mut self) -> io::Result<Option<String>> {
+        let bytes = self.read_bytes(CHUNK)?;
+        if bytes.is_empty() && self.rest.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(self.decode(bytes)))
+    }
+
+    /// Percentage of the file read so far.
+    pub fn progress(&self) -> u64 {
+        self.read * 100 / self.size.max(1)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(len);
+        (&mut self.file).take(len as u64).read_to_end(&mut bytes)?;
+        self.read += bytes.len() as u64;
+        Ok(bytes)
+    }
+
+    /// Decodes `bytes` after the rest of the last chunk and keeps a char cut in half for the
+    /// next one, unless the file ends here.
+    fn decode(&mut self, bytes: Vec<u8>) -> String {
+        let at_end = bytes.is_empty() || self.read >= self.size;
+        let mut bytes = [std::mem::take(&mut self.rest), bytes].concat();
+        if !at_end {
+            self.rest = bytes.split_off(encoding::complete_len(&bytes, self.encoding));
+        }
+        self.replaced |= !encoding::is_valid(&bytes, self.encoding);
+        encoding::decode_chunk(&bytes, self.encoding)
+    }
+}
+
+#[test]
+fn test_load_in_chunks() {
+    let path = std::env::temp_dir().join(format!("revim-large-{}.txt", std::process::id()));
+    let line = "line \u{e9}\n".repeat(1 << 18);
+    std::fs::write(&path, &line).unwrap();
+    let path = path.to_str().unwrap_or_default();
+    assert!(Loader::open(path, 100, None).unwrap().is_none());
+    let (mut text, encoding, mut loader) = Loader::open(path, 1, None).unwrap().unwrap();
+    assert_eq!(encoding, "utf-8");
+    assert!(loader.progress() < 100);
+    while let Some(chunk) = loader.next_chunk().unwrap() {
+        text.push_str(&chunk);
+    }
+    assert_eq!(loader.progress(), 100);
+    assert_eq!(text, line);
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn test_illegal_byte_after_first_chunk() {
+    use crate::{editor::Editor, keymapper::execute_command, Rope};
+    let path = std::env::temp_dir().join(format!("revim-illegal-{}.txt", std::process::id()));
+    let mut bytes = "line \u{e9}\n".repeat(1 << 18).into_bytes();
+    bytes.extend_from_slice(b"bad \xff byte\n");
+    std::fs::write(&path, &bytes).unwrap();
+    let path = path.to_str().unwrap_or_default();
+    let (text, encoding, loader) = Loader::open(path, 1, None).unwrap().unwrap();
+    assert_eq!(encoding, "utf-8");
+    let mut editor = Editor::new(Rope::from_str(&text), Some(path.to_string()));
+    editor.start_loading(editor.buffer, loader);
+    editor.finish_loading(editor.buffer);
+    assert!(editor.readonly && editor.rope.to_string().ends_with("bad \u{fffd} byte\n"));
+    // The file is left as it is unless the write is forced.
+    execute_command(&mut editor, "w");
+    assert!(matches!(editor.error, crate::editor::EditorError::ReadOnly(_)));
+    assert_eq!(std::fs::read(path).unwrap(), bytes);
+    std::fs::remove_file(path).ok();
+}
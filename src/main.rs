@@ -1,10 +1,30 @@
+mod abbrev;
+mod buffer;
 mod commandline;
+mod comment;
+mod complete;
+mod digraph;
+mod edit;
 mod editor;
+mod encoding;
+mod filetype;
+mod format;
+mod hex;
+mod history;
+mod increment;
 mod keymapper;
+mod largefile;
+mod options;
+mod pairs;
 mod render;
+mod spell;
+mod swap;
+mod tab;
+mod undo;
 mod util;
+mod window;
 
-use commandline::{argparser, from_path};
+use commandline::{argparser, open_path};
 use editor::{Editor, Mode};
 use keymapper::*;
 use render::*;
@@ -16,22 +36,52 @@ use ropey::Rope;
 fn main() -> crossterm::Result<()> {
     let mut writer = std::io::stdout();
     let file_path = argparser();
-    let (rope, path) = from_path(file_path);
-    let mut editor = Editor::new(rope, path);
+    let largefile = options::Options::default().largefile;
+    let file = file_path.as_deref().map_or(Ok((Rope::new(), "utf-8", None)), |path| open_path(path, None, largefile));
+    let swap = file_path.as_deref().and_then(swap::find);
+    let mut editor = match file {
+        Ok((rope, encoding, loader)) => {
+            let hex = hex::binary(&rope, encoding);
+            let mut editor = Editor::new(rope, file_path);
+            editor.options.fileencoding = encoding.to_string();
+            editor.hex = hex;
+            if let Some(loader) = loader {
+                editor.start_loading(editor.buffer, loader);
+            }
+            editor
+        }
+        Err(error) => {
+            let mut editor = Editor::new(Rope::new(), file_path);
+            editor.error = error;
+            editor
+        }
+    };
+    editor.load_undo();
+    if let Some(swap) = swap {
+        offer_recovery(&mut editor, swap);
+    }
     let key_map = key_builder();
     render_enter_alt_screen(&mut writer);
     render(&mut writer, &editor);
+    let mut last_key = std::time::Instant::now();
     while editor.is_running {
-        if event::poll(std::time::Duration::from_millis(50))? {
+        // While a file is still being read keys are only checked for between its chunks.
+        let wait = if editor.loaders.is_empty() { 50 } else { 0 };
+        if event::poll(std::time::Duration::from_millis(wait))? {
             let event = event::read()?;
             if let event::Event::Key(key) = event {
-                if let Some(handle) = key_map.get_mapping(&editor.mode, &key) {
-                    handle(&mut editor);
-                }
+                key_map.dispatch(&mut editor, key);
+                last_key = std::time::Instant::now();
             }
             render(&mut writer, &editor);
+        } else if !editor.loaders.is_empty() {
+            editor.load_chunk();
+            render(&mut writer, &editor);
+        } else if editor.changes > 0 && last_key.elapsed().as_millis() >= editor.options.updatetime as u128 {
+            editor.write_swap();
         }
     }
+    editor.remove_swaps();
     render_exit_alt_screen(&mut writer);
     Ok(())
 }
@@ -0,0 +1,240 @@
+use crate::{editor::EditorError, encoding, filetype};
+
+/// Settings changed with `:set`.
+#[derive(Debug)]
+pub struct Options {
+    /// Number formats `Ctrl-A` and `Ctrl-X` recognise besides decimal.
+    pub nrformats: String,
+    /// Width `gq` wraps text at and Insert mode auto wraps at, 0 turns auto wrap off.
+    pub textwidth: usize,
+    /// `t` auto wraps text and `c` auto wraps comments while typing.
+    pub formatoptions: String,
+    /// Language of the file, picks the comment markers `gc` and `gq` use and the default `pairs`.
+    pub filetype: String,
+    /// Line ending new lines get and the file is written with: `unix`, `dos` or `mac`.
+    pub fileformat: String,
+    /// Encoding the file was read in and is written in, one of `encoding::NAMES`.
+    pub fileencoding: String,
+    /// Typing an opening bracket or quote in Insert mode types its closer too.
+    pub autopairs: bool,
+    /// Open and close chars `autopairs` works on, like `()[]{}`.
+    pub pairs: String,
+    /// Underline misspelled words.
+    pub spell: bool,
+    /// Plain text word list spell checking uses, one word per line.
+    pub dictionary: String,
+    /// Where `zg` and `zw` keep the user's good and wrong words.
+    pub spellfile: String,
+    /// Keep unsaved changes in a swap file next to the file to recover them after a crash.
+    pub swapfile: bool,
+    /// Changes after which the swap file is written.
+    pub updatecount: usize,
+    /// Milliseconds without typing after which the swap file is written.
+    pub updatetime: usize,
+    /// Keep the undo history of a file in `undodir` when it is written and read it back when
+    /// the file is opened again unchanged.
+    pub undofile: bool,
+    pub undodir: String,
+    /// Files bigger than this many megabytes are read a chunk at a time without swap file, undo
+    /// or spell checking, 0 turns it off.
+    pub largefile: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            nrformats: "bin,hex".to_string(),
+            textwidth: 0,
+            formatoptions: "tcq".to_string(),
+            filetype: String::new(),
+            fileformat: "unix".to_string(),
+            fileencoding: "utf-8".to_string(),
+            autopairs: false,
+            pairs: filetype::pairs("").to_string(),
+            spell: false,
+            dictionary: "/usr/share/dict/words".to_string(),
+            spellfile: "~/.config/revim/spell.add".to_string(),
+            swapfile: true,
+            updatecount: 200,
+            updatetime: 4000,
+            undofile: false,
+            undodir: "~/.cache/revim/undo".to_string(),
+            largefile: 100,
+        }
+    }
+}
+
+impl Options {
+    /// Every option name `:set` knows.
+    pub const NAMES: &'static [&'static str] = &[
+        "autopairs",
+        "dictionary",
+        "fileencoding",
+        "fileformat",
+        "filetype",
+        "formatoptions",
+        "largefile",
+        "nrformats",
+        "pairs",
+        "spell",
+        "spellfile",
+        "swapfile",
+        "textwidth",
+        "undodir",
+        "undofile",
+        "updatecount",
+        "updatetime",
+    ];
+
+    /// Handles the arguments of `:set` like `nrformats=hex`, `nrformats+=octal` or `nrformats?`.
+    /// Returns the text to show when an option was queried.
+    pub fn set(&mut self, args: &str) -> Result<String, EditorError> {
+        let mut output = Vec::new();
+        for arg in args.split_whitespace() {
+            let (name, op, value) = split_arg(arg);
+            match (name, op) {
+                ("nrformats" | "nf", "?" | "") => output.push(format!("nrformats={}", self.nrformats)),
+                ("nrformats" | "nf", op) => set_list(&mut self.nrformats, op, value),
+                ("textwidth" | "tw", "?" | "") => output.push(format!("textwidth={}", self.textwidth)),
+                ("textwidth" | "tw", op) => set_number(&mut self.textwidth, op, value)?,
+                ("formatoptions" | "fo", "?" | "") => {
+                    output.push(format!("formatoptions={}", self.formatoptions))
+                }
+                ("formatoptions" | "fo", op) => set_flags(&mut self.formatoptions, op, value),
+                ("filetype" | "ft", "?" | "") => output.push(format!("filetype={}", self.filetype)),
+                ("filetype" | "ft", _) => {
+                    self.filetype = value.to_string();
+                    self.pairs = filetype::pairs(value).to_string();
+                }
+                ("fileformat" | "ff", "?" | "") => output.push(format!("fileformat={}", self.fileformat)),
+                ("fileformat" | "ff", _) if matches!(value, "unix" | "dos" | "mac") => {
+                    self.fileformat = value.to_string()
+                }
+                ("fileformat" | "ff", _) => return Err(EditorError::InvalidArgument(value.to_string())),
+                ("fileencoding" | "fenc", "?" | "") => {
+                    output.push(format!("fileencoding={}", self.fileencoding))
+                }
+                ("fileencoding" | "fenc", _) if encoding::NAMES.contains(&value) => {
+                    self.fileencoding = value.to_string()
+                }
+                ("fileencoding" | "fenc", _) => return Err(EditorError::InvalidArgument(value.to_string())),
+                ("autopairs" | "ap", "?") => output.push(bool_value("autopairs", self.autopairs)),
+                ("autopairs" | "ap", "") => self.autopairs = true,
+                ("noautopairs" | "noap", "") => self.autopairs = false,
+                ("autopairs!" | "ap!" | "invautopairs" | "invap", "") => self.autopairs = !self.autopairs,
+                ("pairs", "?" | "") => output.push(format!("pairs={}", self.pairs)),
+                ("pairs", _) if value.chars().count() % 2 != 0 => {
+                    return Err(EditorError::InvalidArgument(value.to_string()))
+                }
+                ("pairs", op) => set_flags(&mut self.pairs, op, value),
+                ("spell", "?") => output.push(bool_value("spell", self.spell)),
+                ("spell", "") => self.spell = true,
+                ("nospell", "") => self.spell = false,
+                ("spell!" | "invspell", "") => self.spell = !self.spell,
+                ("dictionary" | "dict", "?" | "") => output.push(format!("dictionary={}", self.dictionary)),
+                ("dictionary" | "dict", _) => self.dictionary = value.to_string(),
+                ("spellfile" | "spf", "?" | "") => output.push(format!("spellfile={}", self.spellfile)),
+                ("spellfile" | "spf", _) => self.spellfile = value.to_string(),
+                ("swapfile" | "swf", "?") => output.push(bool_value("swapfile", self.swapfile)),
+                ("swapfile" | "swf", "") => self.swapfile = true,
+                ("noswapfile" | "noswf", "") => self.swapfile = false,
+                ("swapfile!" | "swf!" | "invswapfile" | "invswf", "") => self.swapfile = !self.swapfile,
+                ("updatecount" | "uc", "?" | "") => output.push(format!("updatecount={}", self.updatecount)),
+                ("updatecount" | "uc", op) => set_number(&mut self.updatecount, op, value)?,
+                ("updatetime" | "ut", "?" | "") => output.push(format!("updatetime={}", self.updatetime)),
+                ("updatetime" | "ut", op) => set_number(&mut self.updatetime, op, value)?,
+                ("undofile" | "udf", "?") => output.push(bool_value("undofile", self.undofile)),
+                ("undofile" | "udf", "") => self.undofile = true,
+                ("noundofile" | "noudf", "") => self.undofile = false,
+                ("undofile!" | "udf!" | "invundofile" | "invudf", "") => self.undofile = !self.undofile,
+                ("undodir" | "udir", "?" | "") => output.push(format!("undodir={}", self.undodir)),
+                ("undodir" | "udir", _) => self.undodir = value.to_string(),
+                ("largefile" | "lf", "?" | "") => output.push(format!("largefile={}", self.largefile)),
+                ("largefile" | "lf", op) => set_number(&mut self.largefile, op, value)?,
+                _ => return Err(EditorError::UnknownOption(name.to_string())),
+            }
+        }
+        Ok(output.join(" "))
+    }
+}
+
+/// How `:set name?` shows a boolean option.
+fn bool_value(name: &str, value: bool) -> String {
+    if value {
+        name.to_string()
+    } else {
+        format!("no{}", name)
+    }
+}
+
+/// Splits `name+=value` into its name, operator and value.
+fn split_arg(arg: &str) -> (&str, &str, &str) {
+    if let Some(name) = arg.strip_suffix('?') {
+        return (name, "?", "");
+    }
+    match arg.find('=') {
+        Some(i) if i > 0 && matches!(&arg[i - 1..i], "+" | "-" | "^") => {
+            (&arg[..i - 1], &arg[i - 1..=i], &arg[i + 1..])
+        }
+        Some(i) => (&arg[..i], "=", &arg[i + 1..]),
+        None => (arg, "", ""),
+    }
+}
+
+/// Applies `=`, `+=`, `^=` or `-=` to a number option.
+fn set_number(number: &mut usize, op: &str, value: &str) -> Result<(), EditorError> {
+    let value = value
+        .parse::<usize>()
+        .map_err(|_| EditorError::InvalidArgument(value.to_string()))?;
+    *number = match op {
+        "+=" => number.saturating_add(value),
+        "-=" => number.saturating_sub(value),
+        "^=" => number.saturating_mul(value),
+        _ => value,
+    };
+    Ok(())
+}
+
+/// Applies `=`, `+=` or `-=` to an option made of single letter flags.
+fn set_flags(flags: &mut String, op: &str, value: &str) {
+    match op {
+        "+=" => flags.extend(value.chars().filter(|c| !flags.contains(*c)).collect::<Vec<_>>()),
+        "-=" => flags.retain(|c| !value.contains(c)),
+        _ => *flags = value.to_string(),
+    }
+}
+
+/// Applies `=`, `+=`, `^=` or `-=` to a comma separated list option.
+fn set_list(list: &mut String, op: &str, value: &str) {
+    if op == "=" {
+        *list = value.to_string();
+        return;
+    }
+    let mut items = list.split(',').filter(|i| !i.is_empty()).map(String::from).collect::<Vec<_>>();
+    match op {
+        "+=" if !items.iter().any(|i| i == value) => items.push(value.to_string()),
+        "^=" if !items.iter().any(|i| i == value) => items.insert(0, value.to_string()),
+        "-=" => items.retain(|i| i != value),
+        _ => {}
+    }
+    *list = items.join(",");
+}
+
+#[test]
+fn test_set_nrformats() {
+    let mut options = Options::default();
+    options.set("nrformats+=octal nf-=bin").unwrap();
+    assert_eq!(options.nrformats, "hex,octal");
+    assert_eq!(options.set("nf?").unwrap(), "nrformats=hex,octal");
+    assert!(options.set("nosuchoption").is_err());
+}
+
+#[test]
+fn test_set_autopairs_for_filetype() {
+    let mut options = Options::default();
+    options.set("ft=rust ap").unwrap();
+    assert!(options.autopairs);
+    assert_eq!(options.pairs, "()[]{}\"\"");
+    assert_eq!(options.set("noap ap?").unwrap(), "noautopairs");
+    assert!(options.set("pairs=(").is_err());
+}
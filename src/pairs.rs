@@ -0,0 +1,48 @@
+/// What typing a char does when `autopairs` is on.
+#[derive(Debug, PartialEq)]
+pub enum PairAction {
+    /// Type the char and its closer, the cursor goes between them.
+    Pair(char),
+    /// The char is already the next one, move over it.
+    Skip,
+    /// Type the char as usual.
+    Type,
+}
+
+/// Closing char for `open` in `pairs`, a string of open and close chars like `()[]`.
+pub fn closer(pairs: &str, open: char) -> Option<char> {
+    let chars = pairs.chars().collect::<Vec<char>>();
+    chars.chunks(2).find(|pair| pair.len() == 2 && pair[0] == open).map(|pair| pair[1])
+}
+
+fn is_closer(pairs: &str, c: char) -> bool {
+    pairs.chars().skip(1).step_by(2).any(|close| close == c)
+}
+
+/// Decides what typing `c` between `before` and `after` does. Quotes are not paired in the
+/// middle of a word and brackets only pair in front of blanks, closers or the end of the line.
+pub fn on_char(pairs: &str, c: char, before: Option<char>, after: Option<char>) -> PairAction {
+    let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    match closer(pairs, c) {
+        Some(close) if close == c && after == Some(c) => PairAction::Skip,
+        Some(close) if close == c && (word(before) || word(after)) => PairAction::Type,
+        Some(close) if close == c => PairAction::Pair(close),
+        Some(close) if after.is_none_or(|a| a.is_whitespace() || is_closer(pairs, a)) => PairAction::Pair(close),
+        Some(_) => PairAction::Type,
+        None if is_closer(pairs, c) && after == Some(c) => PairAction::Skip,
+        None => PairAction::Type,
+    }
+}
+
+#[test]
+fn test_pair_context() {
+    let pairs = "()[]{}\"\"''";
+    assert_eq!(on_char(pairs, '(', Some('f'), None), PairAction::Pair(')'));
+    assert_eq!(on_char(pairs, '(', None, Some('x')), PairAction::Type);
+    assert_eq!(on_char(pairs, ')', Some('('), Some(')')), PairAction::Skip);
+    assert_eq!(on_char(pairs, '\'', Some('n'), Some('t')), PairAction::Type);
+    assert_eq!(on_char(pairs, '\'', Some('n'), None), PairAction::Type);
+    assert_eq!(on_char(pairs, '"', Some(' '), None), PairAction::Pair('"'));
+    assert_eq!(on_char(pairs, '"', Some('a'), Some('"')), PairAction::Skip);
+    assert_eq!(on_char("()", '\'', None, None), PairAction::Type);
+}
@@ -1,4 +1,4 @@
-use crate::{Editor, Mode};
+use crate::{hex::{self, HexView}, window::Rect, Editor, Mode, Rope};
 use crossterm::{cursor, queue, style, terminal};
 use std::io::{Stdout, Write};
 
@@ -6,13 +6,15 @@ use std::io::{Stdout, Write};
 pub struct ScreenVector {
     pub t: usize,
     pub b: usize,
+    /// Screen column the view starts at, windows right of a vertical split start after 0.
+    pub l: usize,
     pub max_w: usize,
     pub max_h: usize,
 }
 
 impl ScreenVector {
     pub fn new(t: usize, b: usize, max_w: usize, max_h: usize) -> Self {
-        Self { t, b, max_w, max_h }
+        Self { t, b, l: 0, max_w, max_h }
     }
 
     pub fn _origin(&self) -> (usize, usize) {
@@ -34,10 +36,12 @@ pub fn _right(&self) -> usize {
         self.b + self.max_h
     }
 }
-pub(crate) fn screen_size() -> ScreenVector {
-    let (w, h) =
-        crossterm::terminal::size().expect("Your screen size is messed up and I will fix it later");
-    ScreenVector::new(0, 0, w as usize, (h - 2) as usize)
+/// Part of the terminal left for text, less the status and command lines and the tabline.
+/// Without a terminal, like in tests, the screen is 80 by 24.
+pub(crate) fn screen_size(tabline: bool) -> ScreenVector {
+    let (w, h) = crossterm::terminal::size().unwrap_or((80, 24));
+    let tabline = tabline as usize;
+    ScreenVector::new(0, tabline, w as usize, (h as usize).saturating_sub(2 + tabline))
 }
 
 pub(crate) fn render_enter_alt_screen(w: &mut Stdout) {
@@ -59,52 +63,287 @@ pub(crate) fn _render_clear(w: &mut Stdout) {
 
 fn render_text(w: &mut Stdout, editor: &Editor) {
     let screen = &editor.screen;
-    let mut text = String::new();
+    let rect = Rect { x: screen.l, y: screen.b, w: screen.max_w, h: screen.max_h };
+    if let Some(view) = &editor.hex {
+        return render_hex(w, &editor.rope, view, rect);
+    }
+    render_lines(w, &editor.rope, screen.t, rect);
+    render_spelling(w, editor);
+    render_selection(w, editor);
+}
 
-    for (line_num, line) in editor.rope.lines_at(screen.t).enumerate() {
-        if line_num == screen.max_h {break;}
-        text.push_str(&line.chars().collect::<String>())
+/// Draws the lines of `rope` from `top` on into `rect`, padding them to its width.
+fn render_lines(w: &mut Stdout, rope: &Rope, top: usize, rect: Rect) {
+    let mut lines = rope.lines_at(top.min(rope.len_lines()));
+    for row in 0..rect.h {
+        let line = lines.next().map(|line| line.chars().collect::<String>()).unwrap_or_default();
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let mut line = line.chars().map(display_char).collect::<String>().chars().take(rect.w).collect::<String>();
+        format_command_bar(&mut line, rect.w);
+        queue!(
+            w,
+            cursor::MoveTo(rect.x as u16, (rect.y + row) as u16),
+            terminal::DisableLineWrap,
+            style::Print(line),
+        )
+        .expect("Something went wrong while displaying file text.");
     }
+}
 
-    format_text(&mut text, editor.screen.max_w, screen.max_h);
+/// Draws the rows of the hex view of `rope` that keep the cursor of `view` on screen.
+fn render_hex(w: &mut Stdout, rope: &Rope, view: &HexView, rect: Rect) {
+    let top = hex::top(view.top, view.offset / hex::WIDTH, rect.h);
+    for row in 0..rect.h {
+        let mut line = match top + row < hex::rows(rope) {
+            true => hex::format_row(rope, top + row).chars().take(rect.w).collect::<String>(),
+            false => String::new(),
+        };
+        format_command_bar(&mut line, rect.w);
+        queue!(w, cursor::MoveTo(rect.x as u16, (rect.y + row) as u16), style::Print(line))
+            .expect("Something went wrong while displaying the hex view.");
+    }
+}
 
-    queue!(
-        w,
-        cursor::MoveTo(0, 0),
-        terminal::DisableLineWrap,
-        style::Print(text),
-    )
-    .expect("Something went wrong while displaying file text.");
+/// Draws a row at the top with the name of each tab page's active buffer, when there are
+/// several tab pages.
+fn render_tabline(w: &mut Stdout, editor: &Editor) {
+    if editor.tabs.len() < 2 {
+        return;
+    }
+    queue!(w, cursor::MoveTo(0, 0)).expect("Tabline Error");
+    let mut used = 0;
+    for index in 0..editor.tabs.len() {
+        let (name, modified) = editor.tab_label(index);
+        let name = name.rsplit('/').next().unwrap_or_default().to_string();
+        let label = format!(" {}{} {} ", index + 1, if modified { "+" } else { "" }, name);
+        let label = label.chars().take(editor.area.max_w.saturating_sub(used)).collect::<String>();
+        used += label.chars().count();
+        let label = match index == editor.tab {
+            true => style::style(label).attribute(style::Attribute::Bold),
+            false => style::style(label).attribute(style::Attribute::Reverse),
+        };
+        queue!(w, style::PrintStyledContent(label)).expect("Tabline Error");
+    }
+    let fill = " ".repeat(editor.area.max_w.saturating_sub(used));
+    queue!(w, style::PrintStyledContent(style::style(fill).attribute(style::Attribute::Reverse)))
+        .expect("Tabline Error");
+}
+
+/// Draws the windows that are not being edited with their status lines and the separators
+/// between vertical splits.
+fn render_other_windows(w: &mut Stdout, editor: &Editor) {
+    for (id, rect) in editor.window_rects() {
+        let window = match editor.windows.iter().find(|window| window.id == id && id != editor.window) {
+            Some(window) => window,
+            None => continue,
+        };
+        let (rope, name, modified, view) = match editor.other_buffer(window.buffer) {
+            Some(buffer) => (&buffer.rope, buffer.name(), buffer.modified, &buffer.hex),
+            None => (&editor.rope, editor.file_path.as_deref().unwrap_or("[No Name]"), editor.modified, &editor.hex),
+        };
+        let text = Rect { h: rect.h.saturating_sub(1), ..rect };
+        match view {
+            Some(view) => render_hex(w, rope, view, text),
+            None => render_lines(w, rope, window.top, text),
+        }
+        let position = format!("{}/{}", window.cursor.0, window.cursor.1);
+        let mut status = format!(" {}{}", name, if modified { " [+]" } else { "" });
+        format_command_bar(&mut status, rect.w.saturating_sub(position.len()));
+        status.push_str(&position);
+        let status = status.chars().take(rect.w).collect::<String>();
+        queue!(
+            w,
+            cursor::MoveTo(rect.x as u16, (rect.y + text.h) as u16),
+            style::PrintStyledContent(style::style(status).with(style::Color::DarkGrey)),
+        )
+        .expect("Status Bar Error");
+    }
+    for separator in editor.layout.separators(editor.window_area()) {
+        for row in 0..separator.h {
+            queue!(w, cursor::MoveTo(separator.x as u16, (separator.y + row) as u16), style::Print('|'))
+                .expect("Separator Error");
+        }
+    }
+}
+
+/// Screen column of char `col` on `line`, with tabs and control chars as wide as
+/// `display_char` draws them.
+pub(crate) fn display_column(rope: &Rope, line: usize, col: usize) -> usize {
+    rope.line(line).chars().take(col).map(|c| display_char(c).chars().count()).sum()
+}
+
+/// Underlines misspelled words when `spell` is on, but not in large file mode.
+fn render_spelling(w: &mut Stdout, editor: &Editor) {
+    let speller = match &editor.speller {
+        Some(speller) if !editor.largefile => speller,
+        _ => return,
+    };
+    let screen = &editor.screen;
+    let comment = crate::filetype::spell_comment(&editor.options.filetype);
+    let last = (screen.t + screen.max_h).min(editor.rope.len_lines());
+    for line in screen.t..last {
+        let text = crate::edit::line_text(&editor.rope, line);
+        for word in crate::spell::misspelled(speller, &text, comment) {
+            let x = display_column(&editor.rope, line, word.start);
+            if x >= screen.max_w {
+                continue;
+            }
+            let word = text.chars().skip(word.start).take(word.len()).take(screen.max_w - x).collect::<String>();
+            queue!(
+                w,
+                cursor::MoveTo((screen.l + x) as u16, (screen.b + line - screen.t) as u16),
+                style::PrintStyledContent(style::style(word).with(style::Color::Red).attribute(style::Attribute::Underlined)),
+            )
+            .expect("Something went wrong while displaying spelling.");
+        }
+    }
+}
+
+/// Draws the visual selection in reverse video over the text.
+fn render_selection(w: &mut Stdout, editor: &Editor) {
+    if editor.mode != Mode::Visual {
+        return;
+    }
+    let screen = &editor.screen;
+    let range = editor.visual_range();
+    let (first, last) = editor.visual_lines();
+    for line in first.max(screen.t)..=last.min(screen.t + screen.max_h.saturating_sub(1)) {
+        let start = editor.rope.line_to_char(line);
+        let mut x = 0;
+        for (i, c) in editor.rope.line(line).chars().enumerate() {
+            let text = display_char(c);
+            let width = text.chars().count();
+            if range.contains(&(start + i)) && x < screen.max_w && c != '\n' && c != '\r' {
+                queue!(
+                    w,
+                    cursor::MoveTo((screen.l + x) as u16, (screen.b + line - screen.t) as u16),
+                    style::PrintStyledContent(style::style(text).attribute(style::Attribute::Reverse)),
+                )
+                .expect("Something went wrong while displaying the selection.");
+            }
+            x += width;
+        }
+    }
+}
+
+/// Height of the completion popup menu before it starts to scroll.
+const POPUP_HEIGHT: usize = 10;
+
+/// Draws the completion popup menu under the completed text, or above it near the bottom.
+fn render_popup(w: &mut Stdout, editor: &Editor) {
+    let completion = match &editor.completion {
+        Some(completion) => completion,
+        None => return,
+    };
+    let screen = &editor.screen;
+    let height = completion.items.len().min(POPUP_HEIGHT);
+    let width = completion.items.iter().map(|i| i.chars().count()).max().unwrap_or(0) + 2;
+    let y = completion.line.saturating_sub(screen.t);
+    let top = if y + 1 + height <= screen.max_h { y + 1 } else { y.saturating_sub(height) };
+    let left = display_column(&editor.rope, completion.line, completion.col).min(screen.max_w.saturating_sub(width));
+    let selected = completion.selected.unwrap_or(0);
+    let first = selected.saturating_sub(height - 1);
+    for (row, (i, item)) in completion.items.iter().enumerate().skip(first).take(height).enumerate() {
+        let text = format!(" {:width$} ", item, width = width - 2);
+        let color = if completion.selected == Some(i) { style::Color::Grey } else { style::Color::DarkGrey };
+        queue!(
+            w,
+            cursor::MoveTo((screen.l + left) as u16, (screen.b + top + row) as u16),
+            style::PrintStyledContent(style::style(text).with(style::Color::Black).on(color)),
+        )
+        .expect("Something went wrong while displaying the popup menu.");
+    }
 }
 
 fn render_command_bar(w: &mut Stdout, editor: &Editor) {
     // TODO: Compact this.
-    let dot = if Mode::Command == editor.mode { ":" } else { "" };
+    let dot = if Mode::Command == editor.mode { editor.prompt.to_string() } else { String::new() };
     let mut command = format!("{}{}", dot, editor.command.as_str());
-    format_command_bar(&mut command, editor.screen.max_w);
+    format_command_bar(&mut command, editor.area.max_w);
     queue!(
         w,
         cursor::Show,
-        cursor::MoveTo(0, editor.screen.max_h.saturating_add(1) as u16),
+        cursor::MoveTo(0, editor.area.bottom().saturating_add(1) as u16),
         style::Print(command),
     )
     .expect("Command Bar Error");
 }
 
+/// Shows the command line completions in place of the status bar, scrolled so the selected
+/// one is visible.
+fn render_wildmenu(w: &mut Stdout, editor: &Editor) {
+    let wildmenu = match &editor.wildmenu {
+        Some(wildmenu) => wildmenu,
+        None => return,
+    };
+    let width = editor.screen.max_w;
+    let selected = wildmenu.selected.unwrap_or(0);
+    let mut first = 0;
+    while wildmenu.items[first..=selected].iter().map(|i| i.chars().count() + 2).sum::<usize>() + 2 > width
+        && first < selected
+    {
+        first += 1;
+    }
+    let screen = &editor.screen;
+    queue!(w, cursor::MoveTo(screen.l as u16, screen.bottom() as u16)).expect("Wildmenu Error");
+    let mut used = 0;
+    if first > 0 {
+        queue!(w, style::Print("< ")).expect("Wildmenu Error");
+        used += 2;
+    }
+    for (i, item) in wildmenu.items.iter().enumerate().skip(first) {
+        let len = item.chars().count() + 2;
+        if used + len > width {
+            break;
+        }
+        let text = format!("{}  ", item);
+        let text = if wildmenu.selected == Some(i) {
+            style::style(text).with(style::Color::Black).on(style::Color::Yellow)
+        } else {
+            style::style(text)
+        };
+        queue!(w, style::PrintStyledContent(text)).expect("Wildmenu Error");
+        used += len;
+    }
+    queue!(w, style::Print(" ".repeat(width.saturating_sub(used)))).expect("Wildmenu Error");
+}
+
 fn render_status_bar(w: &mut Stdout, editor: &Editor) {
-    let width = editor.screen.max_w.saturating_sub(editor.mode.to_string().len() + editor.cursor.to_string().len());
-    let space = vec![' '; width].iter().collect::<String>();
+    let screen = &editor.screen;
+    let name = editor.file_path.as_deref().unwrap_or("[No Name]");
+    let mut status = format!("{} {}{}", editor.mode, name, if editor.modified { " [+]" } else { "" });
+    let loading = editor.loading().map(|percent| format!("[loading {}%] ", percent)).unwrap_or_default();
+    let position = match &editor.hex {
+        Some(view) => format!("{}[hex] {:08x}", loading, view.offset),
+        None => format!("{}[{}] {}", loading, editor.options.fileformat, editor.cursor),
+    };
+    format_command_bar(&mut status, screen.max_w.saturating_sub(position.len()));
+    status.push_str(&position);
     queue!(
         w,
-        cursor::MoveTo(0, editor.screen.max_h as u16),
-        style::Print(&format!("{}{}{}", editor.mode, space, editor.cursor)),
+        cursor::MoveTo(screen.l as u16, screen.bottom() as u16),
+        style::Print(status.chars().take(screen.max_w).collect::<String>()),
     )
     .expect("Status Bar Error");
 }
 
 fn render_cursor(w: &mut Stdout, editor: &Editor) {
-    let x = if editor.mode == Mode::Command { editor.command.len().saturating_add(1) as u16 } else {editor.cursor.x};
-    let y = if editor.mode == Mode::Command { (1 + editor.screen.bottom()) as u16 } else {editor.cursor.y};
+    let x = if editor.mode == Mode::Command {
+        editor.command[..editor.command_cursor].chars().count().saturating_add(1) as u16
+    } else if let Some(view) = &editor.hex {
+        (view.column() + editor.screen.l) as u16
+    } else {
+        (display_column(&editor.rope, editor.cursor.gy as usize, editor.cursor.gx) + editor.screen.l) as u16
+    };
+    let y = if editor.mode == Mode::Command {
+        (1 + editor.area.bottom()) as u16
+    } else if let Some(view) = &editor.hex {
+        let row = view.offset / hex::WIDTH;
+        (row - hex::top(view.top, row, editor.screen.max_h) + editor.screen.b) as u16
+    } else {
+        editor.cursor.y + editor.screen.b as u16
+    };
     queue!(
         w,
         cursor::Show,
@@ -114,7 +353,7 @@ fn render_cursor(w: &mut Stdout, editor: &Editor) {
 
 fn render_error_message(w: &mut Stdout, editor: &Editor) {
     let x = 0;
-    let y = (editor.screen.bottom().saturating_add(2)) as u16;
+    let y = (editor.area.bottom().saturating_add(2)) as u16;
     queue!(
         w,
         cursor::MoveTo(x, y),
@@ -122,51 +361,53 @@ fn render_error_message(w: &mut Stdout, editor: &Editor) {
     ).expect("Error while rendering cursor");
 }
 
+/// Prints command output on the command bar, output with several lines grows up over the text.
 fn render_output(w: &mut Stdout, editor: &Editor) {
     let x = 0;
-    let y = (1 + editor.screen.bottom()) as u16;
-    queue!(
-        w,
-        cursor::MoveTo(x, y),
-        style::Print(&editor.output),
-    ).expect("Error while rendering cursor");
+    let lines = editor.output.lines().collect::<Vec<&str>>();
+    let bottom = 1 + editor.area.bottom();
+    for (i, line) in lines.iter().enumerate() {
+        let y = (bottom + i + 1).saturating_sub(lines.len()) as u16;
+        let mut line = line.to_string();
+        format_command_bar(&mut line, editor.area.max_w);
+        queue!(
+            w,
+            cursor::MoveTo(x, y),
+            style::Print(line),
+        ).expect("Error while rendering cursor");
+    }
 }
 
 pub(crate) fn render(w: &mut Stdout, editor: &Editor) {
     queue!(w, cursor::Hide,).expect("Error while trying to hide cursor.");
 
+    render_tabline(w, editor);
+    render_other_windows(w, editor);
     render_text(w, editor);
-    render_status_bar(w, &editor);
-    render_command_bar(w, &editor);
+    render_popup(w, editor);
+    render_status_bar(w, editor);
+    render_wildmenu(w, editor);
+    render_command_bar(w, editor);
     // render_line_numbers(&mut writer, &editor);
-    render_error_message(w, &editor);
-    render_output(w, &editor);
-    render_cursor(w, &editor);
+    render_error_message(w, editor);
+    render_output(w, editor);
+    render_cursor(w, editor);
 
     w.flush().expect("Flush Is BROKEN");
 }
 
-fn format_text(text: &mut String, width: usize, height: usize) {
-    let filler = ' ';
-    let mut new = String::new();
-    for (y, line) in text.lines().enumerate() {
-        let spaces = width.saturating_sub(line.len());
-        let blanks = vec![filler; spaces].iter().collect::<String>();
-        new.push_str(&line[..line.len().min(width)].replace("\t", "    "));
-        new.push_str(&blanks);
-        new.push_str("\r\n");
-        if y == height - 1 { break; }
+/// How a char is drawn, tabs as four spaces and control chars like `^A`.
+fn display_char(c: char) -> String {
+    match c {
+        '\t' => "    ".to_string(),
+        c if (c as u32) < 0x20 || c == '\u{7f}' => format!("^{}", char::from_u32(c as u32 ^ 0x40).unwrap_or('?')),
+        c => c.to_string(),
     }
-    for _ in 0..(height.saturating_sub(new.count_char('\n'))) {
-        new.push_str(&vec![filler; width].iter().collect::<String>());
-        new.push_str("\r\n");
-    }
-    *text = new;
 }
 
 fn format_command_bar(line: &mut String, length: usize) {
     let filler = ' ';
-    let spaces = length.saturating_sub(line.len());
+    let spaces = length.saturating_sub(line.chars().count());
     let blanks = vec![filler; spaces].iter().collect::<String>();
     line.push_str(&blanks);
 }
@@ -185,3 +426,11 @@ impl StringCount for String {
     }
 }
 
+
+#[test]
+fn test_display_column() {
+    let rope = Rope::from_str("\ta\u{1}b\nx");
+    assert_eq!(display_column(&rope, 0, 1), 4);
+    assert_eq!(display_column(&rope, 0, 3), 7);
+    assert_eq!(display_column(&rope, 1, 1), 1);
+}
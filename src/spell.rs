@@ -0,0 +1,161 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::ops::Range;
+use crate::util::expand_home;
+
+/// Most suggestions `z=` offers.
+pub const MAX_SUGGESTIONS: usize = 9;
+
+/// Words loaded from the dictionary and the user's `spellfile`.
+#[derive(Debug, Default)]
+pub struct Speller {
+    /// Lower case words from the dictionary and words added with `zg`.
+    words: HashSet<String>,
+    /// Words marked wrong with `zw`, they win over the dictionary.
+    wrong: HashSet<String>,
+}
+
+impl Speller {
+    /// Reads the plain text `dictionary`, one word per line, and the user's `spellfile` where
+    /// wrong words end in `/!`. A missing spellfile is fine, a missing dictionary is not.
+    pub fn load(dictionary: &str, spellfile: &str) -> io::Result<Self> {
+        let mut speller = Self::default();
+        let text = fs::read_to_string(expand_home(dictionary))?;
+        speller.words.extend(text.lines().map(|word| word.trim().to_lowercase()));
+        if let Ok(text) = fs::read_to_string(expand_home(spellfile)) {
+            for word in text.lines().map(str::trim).filter(|word| !word.is_empty()) {
+                match word.strip_suffix("/!") {
+                    Some(word) => speller.wrong.insert(word.to_lowercase()),
+                    None => speller.words.insert(word.to_lowercase()),
+                };
+            }
+        }
+        Ok(speller)
+    }
+
+    pub fn is_good(&self, word: &str) -> bool {
+        let word = word.to_lowercase();
+        !self.wrong.contains(&word) && self.words.contains(&word)
+    }
+
+    /// `zg` and `zw`, remembers `word` as good or wrong and appends it to `spellfile`.
+    pub fn add(&mut self, word: &str, good: bool, spellfile: &str) -> io::Result<()> {
+        let lower = word.to_lowercase();
+        if good {
+            self.wrong.remove(&lower);
+            self.words.insert(lower);
+        } else {
+            self.wrong.insert(lower);
+        }
+        let path = expand_home(spellfile);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}{}", word, if good { "" } else { "/!" })
+    }
+
+    /// Good words closest to `word` by edit distance, keeping a leading capital.
+    pub fn suggest(&self, word: &str) -> Vec<String> {
+        let lower = word.to_lowercase();
+        let len = lower.chars().count();
+        let mut found = self
+            .words
+            .iter()
+            .filter(|w| !self.wrong.contains(*w) && w.chars().count().abs_diff(len) <= 2)
+            .filter_map(|w| Some((edit_distance(&lower, w).filter(|d| *d <= 2)?, w)))
+            .collect::<Vec<(usize, &String)>>();
+        found.sort();
+        let capital = word.chars().next().is_some_and(char::is_uppercase);
+        found
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, w)| match capital {
+                true => w.chars().take(1).flat_map(char::to_uppercase).chain(w.chars().skip(1)).collect(),
+                false => w.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Levenshtein distance where swapping two chars counts as one edit, `None` above 2.
+fn edit_distance(a: &str, b: &str) -> Option<usize> {
+    let (a, b) = (a.chars().collect::<Vec<char>>(), b.chars().collect::<Vec<char>>());
+    let mut rows = vec![(0..=b.len()).collect::<Vec<usize>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = (a[i - 1] != b[j - 1]) as usize;
+            row[j] = (rows[i - 1][j] + 1).min(row[j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        if row.iter().min().is_some_and(|min| *min > 2) {
+            return None;
+        }
+        rows.push(row);
+    }
+    Some(rows[a.len()][b.len()])
+}
+
+/// Char ranges of the words on `line` that are checked. Text files are checked all over, in
+/// source files only comments and strings are, found from the filetype's comment marker.
+fn checked_ranges(line: &[char], comment: Option<&str>) -> Vec<Range<usize>> {
+    let marker = match comment {
+        Some(marker) => marker.chars().collect::<Vec<char>>(),
+        None => return vec![Range { start: 0, end: line.len() }],
+    };
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < line.len() {
+        if line[i..].starts_with(&marker) {
+            ranges.push(i + marker.len()..line.len());
+            break;
+        }
+        if line[i] == '"' {
+            let end = (i + 1..line.len()).find(|j| line[*j] == '"' && line[*j - 1] != '\\').unwrap_or(line.len());
+            ranges.push(i + 1..end);
+            i = end;
+        }
+        i += 1;
+    }
+    ranges
+}
+
+/// Char ranges of the misspelled words on `line`, see `checked_ranges` for `comment`.
+pub fn misspelled(speller: &Speller, line: &str, comment: Option<&str>) -> Vec<Range<usize>> {
+    let chars = line.chars().collect::<Vec<char>>();
+    let mut bad = Vec::new();
+    for range in checked_ranges(&chars, comment) {
+        let mut i = range.start;
+        while i < range.end {
+            if !chars[i].is_alphabetic() {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < range.end && (chars[i].is_alphabetic() || chars[i] == '\'' && chars.get(i + 1).is_some_and(|c| c.is_alphabetic())) {
+                i += 1;
+            }
+            // Words glued to digits or underscores are identifiers, not prose.
+            let glued = |c: Option<&char>| c.is_some_and(|c| c.is_ascii_digit() || *c == '_');
+            let word = chars[start..i].iter().collect::<String>();
+            if i - start > 1 && !glued(chars.get(i)) && !glued(start.checked_sub(1).and_then(|s| chars.get(s))) && !speller.is_good(&word) {
+                bad.push(start..i);
+            }
+        }
+    }
+    bad
+}
+
+#[test]
+fn test_misspelled_words() {
+    let mut speller = Speller::default();
+    speller.words.extend(["the", "quick", "fox", "let"].iter().map(|w| w.to_string()));
+    assert_eq!(misspelled(&speller, "The quikc fox", None), vec![4..9]);
+    assert_eq!(misspelled(&speller, "let qq = \"the fxo\"; // quick brwn", Some("//")), vec![14..17, 29..33]);
+    assert_eq!(speller.suggest("Quikc"), vec!["Quick"]);
+    assert_eq!(speller.suggest("teh"), vec!["the", "let"]);
+}
@@ -0,0 +1,115 @@
+use crate::{commandline::write_to_path, editor::EditorError};
+use ropey::Rope;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// First word of a swap file, followed by the id of the process that wrote it.
+const MAGIC: &str = "revim-swap";
+
+/// A swap file left next to a file that is about to be edited.
+#[derive(Debug)]
+pub struct Swap {
+    pub path: PathBuf,
+    /// Process that wrote it, when it can be read.
+    pub pid: Option<u32>,
+}
+
+/// Names the swap file of `file` can have, `.name.swp` in the same directory and when that
+/// one belongs to another editor `.name.swo`, `.name.swn` and so on down to `.name.swa`.
+fn swap_paths(file: &str) -> impl Iterator<Item = PathBuf> {
+    let path = Path::new(file).to_path_buf();
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    ('a'..='p').rev().map(move |c| path.with_file_name(format!(".{}.sw{}", name, c)))
+}
+
+/// Id of the process that wrote the swap file at `path`, `None` when there is none.
+fn owner(path: &Path) -> Option<u32> {
+    let mut header = String::new();
+    BufReader::new(File::open(path).ok()?).read_line(&mut header).ok()?;
+    header.strip_prefix(MAGIC).and_then(|pid| pid.trim().parse().ok())
+}
+
+fn is_own(path: &Path) -> bool {
+    owner(path) == Some(std::process::id())
+}
+
+/// Saves `rope` as the swap file of `file`, behind a line naming this process. It goes to the
+/// first name not taken by another editor's swap file and replaces the old one only once the
+/// new one is written, like `write_to_path` does for files.
+pub fn write(file: &str, rope: &Rope) -> Result<usize, EditorError> {
+    let path = swap_paths(file)
+        .find(|path| !path.exists() || is_own(path))
+        .ok_or_else(|| EditorError::WriteFailed(format!("{}: too many swap files", file)))?;
+    let mut text = rope.clone();
+    text.insert(0, &format!("{} {}\n", MAGIC, std::process::id()));
+    write_to_path(&text, &path.to_string_lossy(), "utf-8", false)
+}
+
+/// Deletes the swap file this editor wrote for `file`, the ones of other editors stay.
+pub fn remove(file: &str) {
+    for path in swap_paths(file).filter(|path| is_own(path)) {
+        fs::remove_file(path).ok();
+    }
+}
+
+/// Swap file of `file` left by another editor that crashed or is still running.
+pub fn find(file: &str) -> Option<Swap> {
+    swap_paths(file)
+        .find(|path| path.exists() && !is_own(path))
+        .map(|path| Swap { pid: owner(&path), path })
+}
+
+impl Swap {
+    /// The process that wrote the swap file is still running, as far as can be told.
+    pub fn is_running(&self) -> bool {
+        self.pid.is_some_and(|pid| pid != std::process::id() && Path::new(&format!("/proc/{}", pid)).exists())
+    }
+
+    /// Text saved in the swap file.
+    pub fn recover(&self) -> io::Result<Rope> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        reader.read_line(&mut String::new())?;
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        Ok(Rope::from_str(&text))
+    }
+
+    /// Warning shown when the file is opened, with the keys that answer it.
+    pub fn message(&self, file: &str) -> String {
+        let owner = match (self.pid, self.is_running()) {
+            (Some(pid), true) => format!("Process {} may still be editing this file.", pid),
+            _ => "The editor that wrote it is gone, it probably crashed.".to_string(),
+        };
+        format!(
+            "Found a swap file \"{}\" for \"{}\".\n{}\n[R]ecover, [O]pen Read-Only, [D]elete it, [E]dit anyway, [Q]uit, [A]bort:",
+            self.path.display(),
+            file,
+            owner
+        )
+    }
+}
+
+#[test]
+fn test_swap_round_trip() {
+    let dir = std::env::temp_dir().join(format!("revim-swap-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("notes.txt").to_string_lossy().to_string();
+    assert!(find(&file).is_none());
+    write(&file, &Rope::from_str("unsaved\nlines\n")).unwrap();
+    assert!(is_own(&dir.join(".notes.txt.swp")));
+    assert!(find(&file).is_none());
+    // The swap file of another editor is found, kept and not written over.
+    fs::rename(dir.join(".notes.txt.swp"), dir.join(".notes.txt.swo")).unwrap();
+    fs::write(dir.join(".notes.txt.swp"), format!("{} 4294967295\nother\n", MAGIC)).unwrap();
+    let swap = find(&file).unwrap();
+    assert_eq!((swap.path.clone(), swap.pid), (dir.join(".notes.txt.swp"), Some(u32::MAX)));
+    assert!(!swap.is_running());
+    assert_eq!(swap.recover().unwrap(), "other\n");
+    write(&file, &Rope::from_str("mine\n")).unwrap();
+    assert_eq!(fs::read_to_string(dir.join(".notes.txt.swo")).unwrap(), format!("{} {}\nmine\n", MAGIC, std::process::id()));
+    remove(&file);
+    assert!(!dir.join(".notes.txt.swo").exists());
+    assert!(find(&file).is_some());
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,77 @@
+use crate::window::{Layout, Window};
+
+/// A tab page with a window layout of its own. The tab page being shown keeps its windows in
+/// `Editor`, its entry is only filled in again when another tab page is shown.
+#[derive(Debug)]
+pub struct TabPage {
+    pub windows: Vec<Window>,
+    /// Id of the window last edited in this tab page.
+    pub window: usize,
+    pub layout: Layout,
+}
+
+impl Default for TabPage {
+    fn default() -> Self {
+        Self { windows: Vec::new(), window: 0, layout: Layout::Window(0) }
+    }
+}
+
+impl TabPage {
+    /// A tab page with one window on `window`'s buffer.
+    pub fn new(window: Window) -> Self {
+        let id = window.id;
+        Self { windows: vec![window], window: id, layout: Layout::Window(id) }
+    }
+}
+
+/// Index `:tabmove` moves tab page `index` of `len` to. `N` puts it after tab page N, 0
+/// first, `+N` and `-N` move it right or left and no argument puts it last.
+pub fn move_target(arg: &str, index: usize, len: usize) -> Option<usize> {
+    let last = len.saturating_sub(1);
+    let target = match arg.trim() {
+        "" | "$" => last,
+        arg if arg.starts_with('+') => index + arg[1..].parse::<usize>().ok().filter(|_| arg.len() > 1).unwrap_or(1),
+        arg if arg.starts_with('-') => {
+            index.checked_sub(arg[1..].parse::<usize>().ok().filter(|_| arg.len() > 1).unwrap_or(1))?
+        }
+        arg => {
+            let after = arg.parse::<usize>().ok()?;
+            if after > index { after - 1 } else { after }
+        }
+    };
+    (target <= last).then_some(target)
+}
+
+#[test]
+fn test_tab_move_target() {
+    assert_eq!(move_target("", 0, 4), Some(3));
+    assert_eq!(move_target("0", 2, 4), Some(0));
+    assert_eq!(move_target("3", 0, 4), Some(2));
+    assert_eq!(move_target("+", 1, 4), Some(2));
+    assert_eq!(move_target("-2", 1, 4), None);
+    assert_eq!(move_target("+5", 1, 4), None);
+}
+
+#[test]
+fn test_tab_commands() {
+    use crate::{editor::{Editor, EditorError}, keymapper::execute_command};
+    let mut editor = Editor::new(ropey::Rope::from_str("one\n"), None);
+    let height = editor.area.max_h;
+    execute_command(&mut editor, "tabnew");
+    assert_eq!((editor.tabs.len(), editor.tab, editor.buffer), (2, 1, 2));
+    assert_eq!(editor.area.max_h, height - 1);
+    // Closing a tab page shows the one before it again, a modified buffer stays listed.
+    editor.modified = true;
+    execute_command(&mut editor, "tabclose");
+    assert_eq!((editor.tabs.len(), editor.tab, editor.buffer), (1, 0, 1));
+    assert_eq!((editor.rope.to_string().as_str(), editor.area.max_h), ("one\n", height));
+    execute_command(&mut editor, "tabclose");
+    assert!(matches!(editor.error, EditorError::LastTabPage));
+    execute_command(&mut editor, "q");
+    assert!(matches!(editor.error, EditorError::NoWriteSinceLastChange(_)));
+    assert!(editor.is_running);
+    // `:q` closes a tab page before it quits.
+    execute_command(&mut editor, "tabnew");
+    execute_command(&mut editor, "q!");
+    assert_eq!((editor.tabs.len(), editor.is_running), (1, true));
+}
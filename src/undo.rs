@@ -0,0 +1,227 @@
+use ropey::Rope;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// First line of an undo file.
+const MAGIC: &str = "revim-undo 1";
+
+/// Text of a buffer and the cursor as (column, line) at some point of its history.
+#[derive(Debug, Clone)]
+pub struct State {
+    pub rope: Rope,
+    pub cursor: (usize, usize),
+}
+
+/// Undo and redo history of a buffer. Ropes share the chunks a change did not touch, so
+/// keeping a whole copy for every change stays cheap.
+#[derive(Debug)]
+pub struct Undo {
+    past: Vec<State>,
+    future: Vec<State>,
+    /// Changes typed in one Insert mode session are undone together.
+    open: bool,
+    /// Length of `past` when the text was last written, `None` once that text is no longer in
+    /// the history.
+    saved: Option<usize>,
+}
+
+impl Default for Undo {
+    fn default() -> Self {
+        Self { past: Vec::new(), future: Vec::new(), open: false, saved: Some(0) }
+    }
+}
+
+impl Undo {
+    /// Called after every key with the state from before it and whether the key `changed`
+    /// the text, `insert` joins the following changes to this one.
+    pub fn record(&mut self, before: State, changed: bool, insert: bool) {
+        if changed && !self.open {
+            if self.saved > Some(self.past.len()) {
+                self.saved = None;
+            }
+            self.past.push(before);
+            self.future.clear();
+        }
+        self.open = insert && (self.open || changed);
+    }
+
+    /// `u`, returns the state before the last change and keeps `current` for redo.
+    pub fn undo(&mut self, current: State) -> Option<State> {
+        let state = self.past.pop()?;
+        self.future.push(current);
+        self.open = false;
+        Some(state)
+    }
+
+    /// `Ctrl-R`, returns the state undo went back from and keeps `current` for undo.
+    pub fn redo(&mut self, current: State) -> Option<State> {
+        let state = self.future.pop()?;
+        self.past.push(current);
+        self.open = false;
+        Some(state)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.past.is_empty() && self.future.is_empty()
+    }
+
+    /// Remembers that the text is written as it is now, the next change starts a new step.
+    pub fn mark_saved(&mut self) {
+        self.open = false;
+        self.saved = Some(self.past.len());
+    }
+
+    /// Undo or redo went back to the text that was last written.
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.past.len())
+    }
+
+    /// Writes the history leading to `rope` to `path`. Every state is kept as the edit that
+    /// turns its neighbour closer to `rope` into it, with a hash of `rope` so the history is
+    /// only read back for the same text.
+    pub fn save(&self, rope: &Rope, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}\n{:016x}\n{} {}", MAGIC, hash(rope), self.past.len(), self.future.len())?;
+        for states in [&self.past, &self.future] {
+            let mut next = rope.clone();
+            for state in states.iter().rev() {
+                let (start, removed, inserted) = diff(&next, &state.rope);
+                let (col, line) = state.cursor;
+                writeln!(writer, "{} {} {} {} {}", start, removed, inserted.len(), col, line)?;
+                writer.write_all(inserted.as_bytes())?;
+                next = state.rope.clone();
+            }
+        }
+        writer.flush()
+    }
+
+    /// Reads the history `save` wrote for `rope`, `None` when the file was changed since.
+    pub fn load(rope: &Rope, path: &Path) -> io::Result<Option<Self>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "not an undo file");
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut line = String::new();
+        let mut next_line = |reader: &mut BufReader<File>| {
+            line.clear();
+            reader.read_line(&mut line).map(|_| line.trim_end().to_string())
+        };
+        if next_line(&mut reader)? != MAGIC {
+            return Err(invalid());
+        }
+        if next_line(&mut reader)? != format!("{:016x}", hash(rope)) {
+            return Ok(None);
+        }
+        let counts = next_line(&mut reader)?;
+        let counts = counts.split(' ').map(|n| n.parse::<usize>().map_err(|_| invalid())).collect::<Result<Vec<_>, _>>()?;
+        let mut undo = Self::default();
+        for (list, count) in [(&mut undo.past, counts[0]), (&mut undo.future, *counts.get(1).ok_or_else(invalid)?)] {
+            let mut next = rope.clone();
+            for _ in 0..count {
+                let fields = next_line(&mut reader)?;
+                let fields = fields.split(' ').map(|n| n.parse::<usize>().map_err(|_| invalid())).collect::<Result<Vec<_>, _>>()?;
+                let [start, removed, len, col, line] = fields[..] else { return Err(invalid()) };
+                let mut inserted = vec![0; len];
+                reader.read_exact(&mut inserted)?;
+                let inserted = String::from_utf8(inserted).map_err(|_| invalid())?;
+                if start + removed > next.len_chars() {
+                    return Err(invalid());
+                }
+                let mut state = next.clone();
+                state.remove(start..start + removed);
+                state.insert(start, &inserted);
+                next = state.clone();
+                list.push(State { rope: state, cursor: (col, line) });
+            }
+            list.reverse();
+        }
+        undo.saved = Some(undo.past.len());
+        Ok(Some(undo))
+    }
+
+    /// Forgets the history, for when the file was read again.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Undo file of `file` in `dir`, named after the full path with `/` turned into `%`.
+pub fn undo_path(dir: &Path, file: &str) -> PathBuf {
+    let full = fs::canonicalize(file).unwrap_or_else(|_| PathBuf::from(file));
+    dir.join(full.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "%"))
+}
+
+/// FNV-1a hash of the text, it stays the same across builds unlike the std hasher.
+fn hash(rope: &Rope) -> u64 {
+    rope.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// The edit that turns `from` into `to` as its start char, the number of chars it removes
+/// and the text it inserts.
+fn diff(from: &Rope, to: &Rope) -> (usize, usize, String) {
+    let prefix = from.chars().zip(to.chars()).take_while(|(a, b)| a == b).count();
+    let rest = (from.len_chars() - prefix).min(to.len_chars() - prefix);
+    let (mut a, mut b) = (from.chars_at(from.len_chars()), to.chars_at(to.len_chars()));
+    let suffix = std::iter::from_fn(|| Some((a.prev()?, b.prev()?)));
+    let suffix = suffix.take(rest).take_while(|(a, b)| a == b).count();
+    let inserted = to.slice(prefix..to.len_chars() - suffix).to_string();
+    (prefix, from.len_chars() - suffix - prefix, inserted)
+}
+
+#[test]
+fn test_undo_groups_insert_session() {
+    let state = |text: &str| State { rope: Rope::from_str(text), cursor: (0, 0) };
+    let mut undo = Undo::default();
+    undo.record(state("a"), true, true);
+    undo.record(state("ab"), true, true);
+    undo.record(state("abc"), false, false);
+    undo.record(state("abc"), true, false);
+    assert_eq!(undo.undo(state("c")).unwrap().rope, "abc");
+    undo.record(state("c"), false, false);
+    assert_eq!(undo.undo(state("abc")).unwrap().rope, "a");
+    assert_eq!(undo.redo(state("a")).unwrap().rope, "abc");
+    assert!(undo.undo(state("a")).is_some());
+    assert!(undo.undo(state("a")).is_none());
+}
+
+#[test]
+fn test_undo_file_round_trip() {
+    let state = |text: &str, line: usize| State { rope: Rope::from_str(text), cursor: (0, line) };
+    let mut undo = Undo::default();
+    undo.record(state("one\n", 0), true, false);
+    undo.record(state("one\ntwo\n", 1), true, false);
+    undo.record(state("one\nthree\n", 1), true, false);
+    let redone = undo.undo(state("zero\none\nthree\n", 0)).unwrap();
+    let path = std::env::temp_dir().join(format!("revim-undo-{}", std::process::id()));
+    undo.save(&redone.rope, &path).unwrap();
+    assert!(Undo::load(&Rope::from_str("changed\n"), &path).unwrap().is_none());
+    let mut loaded = Undo::load(&redone.rope, &path).unwrap().unwrap();
+    fs::remove_file(&path).ok();
+    assert_eq!(loaded.redo(state("", 0)).unwrap().rope, "zero\none\nthree\n");
+    assert!(loaded.undo(state("", 0)).is_some());
+    let oldest = loaded.undo(state("", 0)).and_then(|_| loaded.undo(state("", 0))).unwrap();
+    assert_eq!((oldest.rope.to_string(), oldest.cursor), ("one\n".to_string(), (0, 0)));
+}
+
+#[test]
+fn test_undo_back_to_saved() {
+    let state = |text: &str| State { rope: Rope::from_str(text), cursor: (0, 0) };
+    let mut undo = Undo::default();
+    undo.record(state("a"), true, false);
+    undo.mark_saved();
+    undo.record(state("ab"), true, false);
+    assert!(!undo.is_saved());
+    undo.undo(state("abc"));
+    assert!(undo.is_saved());
+    undo.undo(state("ab"));
+    assert!(!undo.is_saved());
+    undo.redo(state("a"));
+    assert!(undo.is_saved());
+    // A change made after undoing past the written text drops it from the history.
+    undo.undo(state("ab"));
+    undo.record(state("a"), true, false);
+    undo.undo(state("ax"));
+    assert!(!undo.is_saved());
+}
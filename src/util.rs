@@ -17,3 +17,11 @@ where
         x - y
     }
 }
+
+/// Expands a leading `~/` to the home directory.
+pub fn expand_home(path: &str) -> std::path::PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => std::env::var("HOME").map(|home| std::path::PathBuf::from(home).join(rest)).unwrap_or_else(|_| rest.into()),
+        None => std::path::PathBuf::from(path),
+    }
+}
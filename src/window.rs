@@ -0,0 +1,229 @@
+/// A view onto a buffer. The window being edited keeps its cursor and viewport in `Editor`,
+/// its entry is only filled in again when another window is entered.
+#[derive(Debug, Default, Clone)]
+pub struct Window {
+    pub id: usize,
+    pub buffer: usize,
+    /// Cursor as (column, line) and the first line on screen.
+    pub cursor: (usize, usize),
+    pub top: usize,
+}
+
+/// Part of the screen a window or split covers, `h` includes the status line.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub w: usize,
+    pub h: usize,
+}
+
+/// Smallest window, one line of text and the status line by one column.
+const MIN_HEIGHT: usize = 2;
+const MIN_WIDTH: usize = 1;
+
+/// How the windows share the screen. A split gives `size` rows, or columns when `vertical`,
+/// to its first part and the rest to its second, less a separator column between vertical
+/// parts.
+#[derive(Debug)]
+pub enum Layout {
+    Window(usize),
+    Split { vertical: bool, size: usize, first: Box<Layout>, second: Box<Layout> },
+}
+
+/// Cuts `rect` in two at `size`, keeping both parts at least the smallest window.
+fn split_rect(rect: Rect, vertical: bool, size: usize) -> (Rect, Rect) {
+    if vertical {
+        let size = size.min(rect.w.saturating_sub(MIN_WIDTH + 1)).max(MIN_WIDTH);
+        let rest = rect.w.saturating_sub(size + 1);
+        (Rect { w: size, ..rect }, Rect { x: rect.x + size + 1, w: rest, ..rect })
+    } else {
+        let size = size.min(rect.h.saturating_sub(MIN_HEIGHT)).max(MIN_HEIGHT);
+        let rest = rect.h.saturating_sub(size);
+        (Rect { h: size, ..rect }, Rect { y: rect.y + size, h: rest, ..rect })
+    }
+}
+
+impl Layout {
+    pub fn contains(&self, id: usize) -> bool {
+        match self {
+            Self::Window(window) => *window == id,
+            Self::Split { first, second, .. } => first.contains(id) || second.contains(id),
+        }
+    }
+
+    /// Where every window goes when the layout fills `rect`, from top left to bottom right.
+    pub fn rects(&self, rect: Rect) -> Vec<(usize, Rect)> {
+        match self {
+            Self::Window(id) => vec![(*id, rect)],
+            Self::Split { vertical, size, first, second } => {
+                let (a, b) = split_rect(rect, *vertical, *size);
+                let mut rects = first.rects(a);
+                rects.extend(second.rects(b));
+                rects
+            }
+        }
+    }
+
+    /// Separator columns between vertical splits.
+    pub fn separators(&self, rect: Rect) -> Vec<Rect> {
+        match self {
+            Self::Window(_) => Vec::new(),
+            Self::Split { vertical, size, first, second } => {
+                let (a, b) = split_rect(rect, *vertical, *size);
+                let mut separators = first.separators(a);
+                separators.extend(second.separators(b));
+                if *vertical {
+                    separators.push(Rect { x: a.x + a.w, w: 1, ..a });
+                }
+                separators
+            }
+        }
+    }
+
+    /// Splits window `id`, the `new` one gets the first `size` rows or columns.
+    pub fn split(&mut self, id: usize, new: usize, vertical: bool, size: usize) -> bool {
+        match self {
+            Self::Window(window) if *window == id => {
+                let (first, second) = (Box::new(Self::Window(new)), Box::new(Self::Window(id)));
+                *self = Self::Split { vertical, size, first, second };
+                true
+            }
+            Self::Window(_) => false,
+            Self::Split { first, second, .. } => {
+                first.split(id, new, vertical, size) || second.split(id, new, vertical, size)
+            }
+        }
+    }
+
+    /// Removes window `id`, the other part of its split takes over the space.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let (first, second) = match self {
+            Self::Window(_) => return false,
+            Self::Split { first, second, .. } => (first, second),
+        };
+        let rest = match (&**first, &**second) {
+            (Self::Window(window), _) if *window == id => std::mem::replace(&mut **second, Self::Window(0)),
+            (_, Self::Window(window)) if *window == id => std::mem::replace(&mut **first, Self::Window(0)),
+            _ => return first.remove(id) || second.remove(id),
+        };
+        *self = rest;
+        true
+    }
+
+    /// Grows window `id` by `delta` rows, or columns when `vertical`, in the closest split
+    /// of that direction around it.
+    pub fn resize(&mut self, id: usize, vertical: bool, delta: isize, rect: Rect) -> bool {
+        let (split_vertical, size, first, second) = match self {
+            Self::Window(_) => return false,
+            Self::Split { vertical, size, first, second } => (*vertical, size, first, second),
+        };
+        let (a, b) = split_rect(rect, split_vertical, *size);
+        if first.resize(id, vertical, delta, a) || second.resize(id, vertical, delta, b) {
+            return true;
+        }
+        if split_vertical != vertical || !first.contains(id) && !second.contains(id) {
+            return false;
+        }
+        let delta = if first.contains(id) { delta } else { -delta };
+        let current = if vertical { a.w } else { a.h };
+        let (grown, _) = split_rect(rect, vertical, current.saturating_add_signed(delta));
+        *size = if vertical { grown.w } else { grown.h };
+        true
+    }
+
+    /// Number of windows side by side in the direction of `vertical`.
+    fn count(&self, vertical: bool) -> usize {
+        match self {
+            Self::Window(_) => 1,
+            Self::Split { vertical: v, first, second, .. } if *v == vertical => {
+                first.count(vertical) + second.count(vertical)
+            }
+            Self::Split { first, second, .. } => first.count(vertical).max(second.count(vertical)),
+        }
+    }
+
+    /// `Ctrl-W =`, gives all windows about the same size.
+    pub fn equalize(&mut self, rect: Rect) {
+        if let Self::Split { vertical, size, first, second } = self {
+            let (before, all) = (first.count(*vertical), first.count(*vertical) + second.count(*vertical));
+            *size = match vertical {
+                true => (rect.w.saturating_sub(all - 1)) * before / all + before - 1,
+                false => rect.h * before / all,
+            };
+            let (a, b) = split_rect(rect, *vertical, *size);
+            first.equalize(a);
+            second.equalize(b);
+        }
+    }
+}
+
+/// Window next to `from` in the direction of `h`, `j`, `k` or `l`. Of several the one beside
+/// the screen position `cursor` as (column, row) wins.
+pub fn neighbour(rects: &[(usize, Rect)], from: usize, direction: char, cursor: (usize, usize)) -> Option<usize> {
+    let r = rects.iter().find(|(id, _)| *id == from)?.1;
+    let overlaps = |start: usize, len: usize, other: usize, other_len: usize| start <= other + other_len && other <= start + len;
+    let beside = rects.iter().filter(|(_, o)| match direction {
+        'h' => o.x + o.w + 1 == r.x && overlaps(o.y, o.h, r.y, r.h),
+        'l' => r.x + r.w + 1 == o.x && overlaps(o.y, o.h, r.y, r.h),
+        'k' => o.y + o.h == r.y && overlaps(o.x, o.w, r.x, r.w),
+        _ => r.y + r.h == o.y && overlaps(o.x, o.w, r.x, r.w),
+    });
+    let beside = beside.collect::<Vec<&(usize, Rect)>>();
+    let at_cursor = beside.iter().find(|(_, o)| match direction {
+        'h' | 'l' => (o.y..o.y + o.h).contains(&cursor.1),
+        _ => (o.x..=o.x + o.w).contains(&cursor.0),
+    });
+    at_cursor.or(beside.first()).map(|(id, _)| *id)
+}
+
+#[test]
+fn test_split_layout() {
+    let screen = Rect { x: 0, y: 0, w: 81, h: 24 };
+    let mut layout = Layout::Window(1);
+    layout.split(1, 2, true, 40);
+    layout.split(1, 3, false, 12);
+    let rects = layout.rects(screen);
+    assert_eq!(
+        rects,
+        vec![
+            (2, Rect { x: 0, y: 0, w: 40, h: 24 }),
+            (3, Rect { x: 41, y: 0, w: 40, h: 12 }),
+            (1, Rect { x: 41, y: 12, w: 40, h: 12 }),
+        ]
+    );
+    assert_eq!(layout.separators(screen), vec![Rect { x: 40, y: 0, w: 1, h: 24 }]);
+    assert_eq!(neighbour(&rects, 2, 'l', (10, 15)), Some(1));
+    assert_eq!(neighbour(&rects, 1, 'k', (50, 15)), Some(3));
+    assert_eq!(neighbour(&rects, 1, 'j', (50, 15)), None);
+    layout.resize(1, false, 2, screen);
+    assert_eq!(layout.rects(screen)[2].1, Rect { x: 41, y: 10, w: 40, h: 14 });
+    layout.remove(2);
+    assert_eq!(layout.rects(screen)[0].1, Rect { x: 0, y: 0, w: 81, h: 10 });
+}
+
+#[test]
+fn test_window_commands() {
+    use crate::{editor::{Editor, EditorError}, keymapper::execute_command};
+    let other = std::env::temp_dir().join("revim-window-test").join("other.txt");
+    let mut editor = Editor::new(ropey::Rope::from_str("one\n"), None);
+    execute_command(&mut editor, "vsplit");
+    execute_command(&mut editor, &format!("e {}", other.display()));
+    assert_eq!((editor.windows.len(), editor.window, editor.buffer), (2, 2, 2));
+    // The window that gets the space of a closed one is entered with its own buffer.
+    execute_command(&mut editor, "close");
+    assert_eq!((editor.windows.len(), editor.window, editor.buffer), (1, 1, 1));
+    assert_eq!(editor.rope.to_string(), "one\n");
+    execute_command(&mut editor, "close");
+    assert!(matches!(editor.error, EditorError::LastWindow));
+    // `:q` closes a split window of a modified buffer, but not the last one.
+    execute_command(&mut editor, "split");
+    editor.modified = true;
+    execute_command(&mut editor, "q");
+    assert_eq!(editor.windows.len(), 1);
+    execute_command(&mut editor, "q");
+    assert!(matches!(editor.error, EditorError::NoWriteSinceLastChange(_)));
+    assert!(editor.is_running);
+    execute_command(&mut editor, "q!");
+    assert!(!editor.is_running);
+}